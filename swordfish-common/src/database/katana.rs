@@ -1,8 +1,9 @@
 use crate::database;
 use crate::error;
-use crate::structs::Character;
+use crate::structs::{Card, Character, Fingerprint};
 use mongodb::bson;
 use mongodb::bson::doc;
+use mongodb::options::{FindOptions, UpdateOptions};
 use mongodb::Collection;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::OnceCell;
@@ -10,9 +11,11 @@ use tokio::task;
 use tracing::trace;
 
 pub static KATANA: OnceCell<Collection<Character>> = OnceCell::const_new();
+pub static KATANA_FINGERPRINTS: OnceCell<Collection<Fingerprint>> = OnceCell::const_new();
+pub static KATANA_CARDS: OnceCell<Collection<Card>> = OnceCell::const_new();
 
 ///
-/// Initialize the "katana" collection in MongoDB
+/// Initialize the "katana", "katana_fingerprints" and "katana_cards" collections in MongoDB
 ///
 /// This method is called automatically when you initialize the
 /// database module.
@@ -26,6 +29,22 @@ pub fn init() {
                 .collection::<Character>("katana"),
         )
         .unwrap();
+    KATANA_FINGERPRINTS
+        .set(
+            database::MONGO_DATABASE
+                .get()
+                .unwrap()
+                .collection::<Fingerprint>("katana_fingerprints"),
+        )
+        .unwrap();
+    KATANA_CARDS
+        .set(
+            database::MONGO_DATABASE
+                .get()
+                .unwrap()
+                .collection::<Card>("katana_cards"),
+        )
+        .unwrap();
 }
 
 pub async fn query_character(name: &String, series: &String) -> Option<Character> {
@@ -64,6 +83,109 @@ pub async fn query_character_regex(name: &String, series: &String) -> Option<Cha
     }
 }
 
+async fn find_characters(
+    filter: bson::Document,
+    limit: Option<i64>,
+) -> Result<Vec<Character>, String> {
+    let options = FindOptions::builder().limit(limit).build();
+    let mut characters: Vec<Character> = Vec::new();
+    let mut cursor = match KATANA.get().unwrap().find(filter, options).await {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            error!("Failed to get cursor: {}", e);
+            return Err(format!("Failed to get cursor: {}", e));
+        }
+    };
+    loop {
+        match cursor.advance().await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                error!("Failed to advance cursor: {}", e);
+                return Err(format!("Failed to advance cursor: {}", e));
+            }
+        }
+        match cursor.deserialize_current() {
+            Ok(character) => characters.push(character),
+            Err(e) => {
+                error!("Failed to get document: {}", e);
+            }
+        }
+    }
+    Ok(characters)
+}
+
+///
+/// Queries the database for all series matching the regex.
+///
+/// An empty regex matches nothing rather than every series.
+///
+pub async fn query_series_regex(series: &String) -> Result<Vec<String>, String> {
+    if series.is_empty() {
+        return Ok(Vec::new());
+    }
+    match KATANA
+        .get()
+        .unwrap()
+        .distinct(
+            "series",
+            doc! {
+                "series": {"$regex": series, "$options" : "i"}
+            },
+            None,
+        )
+        .await
+    {
+        Ok(series) => Ok(series
+            .into_iter()
+            .filter_map(|s| s.as_str().map(|s| s.to_string()))
+            .collect()),
+        Err(e) => {
+            error!("Failed to get series: {}", e);
+            Err(format!("Failed to get series: {}", e))
+        }
+    }
+}
+
+///
+/// Queries the database for all distinct values of the field, e.g. "name" or "series".
+///
+pub async fn query_distinct(field: &str) -> Result<Vec<String>, String> {
+    match KATANA.get().unwrap().distinct(field, None, None).await {
+        Ok(values) => Ok(values
+            .into_iter()
+            .filter_map(|v| v.as_str().map(|v| v.to_string()))
+            .collect()),
+        Err(e) => {
+            error!("Failed to get distinct {}: {}", field, e);
+            Err(format!("Failed to get distinct {}: {}", field, e))
+        }
+    }
+}
+
+///
+/// Queries the database for all characters in the series.
+///
+pub async fn query_characters_in_series(series: &String) -> Result<Vec<Character>, String> {
+    find_characters(doc! { "series": series }, None).await
+}
+
+///
+/// Queries the database for characters with the name matching the regex, in any series.
+///
+pub async fn query_characters_name_regex(
+    name: &String,
+    limit: i64,
+) -> Result<Vec<Character>, String> {
+    find_characters(
+        doc! {
+            "name": {"$regex": name, "$options" : "i"}
+        },
+        Some(limit),
+    )
+    .await
+}
+
 async fn query_characters_regex_internal(
     stage1: bson::Document,
     names: Vec<&String>,
@@ -343,3 +465,149 @@ pub async fn write_characters(cards: Vec<Character>) -> Result<(), String> {
     }
     Ok(())
 }
+
+///
+/// Insert the characters missing from the database, leaving the existing ones (and their
+/// wishlist) untouched.
+///
+pub async fn insert_missing_characters(characters: Vec<Character>) -> Result<(), String> {
+    let options = UpdateOptions::builder().upsert(true).build();
+    for character in characters {
+        trace!("Inserting character if missing: {:?}", character);
+        let fields = match bson::to_document(&character) {
+            Ok(document) => document,
+            Err(e) => return Err(format!("Failed to serialize character: {}", e)),
+        };
+        match KATANA
+            .get()
+            .unwrap()
+            .update_one(
+                doc! {"name": character.name.clone(), "series": character.series.clone()},
+                doc! {"$setOnInsert": fields},
+                options.clone(),
+            )
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => return Err(format!("Failed to insert character: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+///
+/// Insert the card, or update the card with the same code.
+///
+/// Fields missing from the card (e.g. the image of cards seen in a collection) are kept.
+///
+pub async fn write_card(mut card: Card) -> Result<(), String> {
+    let current_time_ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    card.last_update_ts = current_time_ts.as_secs() as i64;
+    trace!("Writing card: {:?}", card);
+    let fields: bson::Document = match bson::to_document(&card) {
+        Ok(document) => document
+            .into_iter()
+            .filter(|(_, value)| *value != bson::Bson::Null)
+            .collect(),
+        Err(e) => return Err(format!("Failed to serialize card: {}", e)),
+    };
+    let options = UpdateOptions::builder().upsert(true).build();
+    match KATANA_CARDS
+        .get()
+        .unwrap()
+        .update_one(
+            doc! {"code": card.code.clone()},
+            doc! {"$set": fields},
+            options,
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write card: {}", e)),
+    }
+}
+
+///
+/// Queries the database for all card art fingerprints.
+///
+pub async fn query_fingerprints() -> Result<Vec<Fingerprint>, String> {
+    let mut fingerprints: Vec<Fingerprint> = Vec::new();
+    let mut cursor = match KATANA_FINGERPRINTS.get().unwrap().find(None, None).await {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            error!("Failed to get cursor: {}", e);
+            return Err(format!("Failed to get cursor: {}", e));
+        }
+    };
+    loop {
+        match cursor.advance().await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                error!("Failed to advance cursor: {}", e);
+                return Err(format!("Failed to advance cursor: {}", e));
+            }
+        }
+        match cursor.deserialize_current() {
+            Ok(fingerprint) => fingerprints.push(fingerprint),
+            Err(e) => {
+                error!("Failed to get document: {}", e);
+            }
+        }
+    }
+    Ok(fingerprints)
+}
+
+///
+/// Records that the card art with the hash was identified as the character.
+///
+pub async fn write_fingerprint(hash: i64, name: &String, series: &String) -> Result<(), String> {
+    let current_time_ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    let options = UpdateOptions::builder().upsert(true).build();
+    match KATANA_FINGERPRINTS
+        .get()
+        .unwrap()
+        .update_one(
+            doc! {
+                "hash": hash,
+                "name": name,
+                "series": series
+            },
+            doc! {
+                "$inc": {"count": 1},
+                "$set": {"last_update_ts": current_time_ts.as_secs() as i64}
+            },
+            options,
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write fingerprint: {}", e)),
+    }
+}
+
+///
+/// Forget that the card art with the hash is the character.
+///
+pub async fn delete_fingerprint(hash: i64, name: &String, series: &String) -> Result<(), String> {
+    match KATANA_FINGERPRINTS
+        .get()
+        .unwrap()
+        .delete_many(
+            doc! {
+                "hash": hash,
+                "name": name,
+                "series": series
+            },
+            None,
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to delete fingerprint: {}", e)),
+    }
+}
@@ -6,6 +6,7 @@ pub use tracing::{debug, error, info, trace, warn};
 use tracing_subscriber::{self, fmt, EnvFilter};
 pub mod constants;
 pub mod database;
+pub mod parsers;
 pub mod structs;
 pub mod utils;
 
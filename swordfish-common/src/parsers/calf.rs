@@ -0,0 +1,24 @@
+use crate::constants;
+use crate::parsers::{MessageData, Parsed, Parser};
+use crate::utils::katana as utils;
+
+///
+/// Calf's analysis of a Katana drop.
+///
+pub struct DropAnalysis;
+
+impl Parser for DropAnalysis {
+    fn name(&self) -> &'static str {
+        "Calf Analysis"
+    }
+    fn source(&self) -> u64 {
+        constants::CALF_ID
+    }
+    fn detect(&self, message: &MessageData) -> bool {
+        message.content.contains("Apricot v6 Drop Analysis Engine")
+    }
+    fn parse(&self, message: &MessageData) -> Parsed {
+        let (cards, diagnostics) = utils::parse_cards_from_calf_analysis(&message.content);
+        Parsed::characters(cards, diagnostics)
+    }
+}
@@ -0,0 +1,244 @@
+use crate::constants;
+use crate::parsers::{MessageData, Parsed, Parser, Record};
+use crate::structs::{Card, Character};
+use crate::utils::katana as utils;
+
+///
+/// The card and its character.
+///
+/// Characters without their wishlist count are only added if they're missing from the
+/// database. Cards shown without their code are only used for their character.
+///
+fn card_records(card: Card) -> Vec<Record> {
+    let mut records: Vec<Record> = vec![Record::Character(Character {
+        wishlist: card.wishlist,
+        name: card.name.clone(),
+        series: card.series.clone(),
+        last_update_ts: 0,
+    })];
+    if !card.code.is_empty() {
+        records.push(Record::Card(card));
+    }
+    records
+}
+
+///
+/// `kc`, a page of a collection in any sort order.
+///
+pub struct CardCollection;
+
+impl Parser for CardCollection {
+    fn name(&self) -> &'static str {
+        "Katana 'Card Collection'"
+    }
+    fn source(&self) -> u64 {
+        constants::KATANA_ID
+    }
+    fn detect(&self, message: &MessageData) -> bool {
+        match message.embed() {
+            Some(embed) => {
+                embed.author.as_deref() == Some("Card Collection") && embed.description.is_some()
+            }
+            None => false,
+        }
+    }
+    fn parse(&self, message: &MessageData) -> Parsed {
+        let description = match message.embed().and_then(|embed| embed.description.as_ref()) {
+            Some(description) => description,
+            None => return Parsed::default(),
+        };
+        let (cards, diagnostics) = utils::parse_cards_from_katana_kc(description);
+        Parsed {
+            records: cards.into_iter().flat_map(card_records).collect(),
+            diagnostics,
+        }
+    }
+}
+
+///
+/// `klu`, a single character.
+///
+pub struct CharacterLookup;
+
+impl Parser for CharacterLookup {
+    fn name(&self) -> &'static str {
+        "Katana 'Character Lookup'"
+    }
+    fn source(&self) -> u64 {
+        constants::KATANA_ID
+    }
+    fn detect(&self, message: &MessageData) -> bool {
+        match message.embed() {
+            Some(embed) => {
+                embed.title.as_deref() == Some("Character Lookup") && embed.description.is_some()
+            }
+            None => false,
+        }
+    }
+    fn parse(&self, message: &MessageData) -> Parsed {
+        match message.embed().and_then(|embed| embed.description.as_ref()) {
+            Some(description) => {
+                let (card, diagnostics) = utils::parse_cards_from_katana_klu_lookup(description);
+                Parsed::characters(card.into_iter().collect(), diagnostics)
+            }
+            None => Parsed::default(),
+        }
+    }
+}
+
+///
+/// `klu` with several matching characters.
+///
+pub struct CharacterResults;
+
+impl Parser for CharacterResults {
+    fn name(&self) -> &'static str {
+        "Katana 'Character Results'"
+    }
+    fn source(&self) -> u64 {
+        constants::KATANA_ID
+    }
+    fn detect(&self, message: &MessageData) -> bool {
+        match message.embed() {
+            Some(embed) => {
+                embed.title.as_deref() == Some("Character Results") && !embed.fields.is_empty()
+            }
+            None => false,
+        }
+    }
+    fn parse(&self, message: &MessageData) -> Parsed {
+        match message.embed().and_then(|embed| embed.fields.first()) {
+            Some((_, value)) => {
+                let (cards, diagnostics) = utils::parse_cards_from_katana_klu_results(value);
+                Parsed::characters(cards, diagnostics)
+            }
+            None => Parsed::default(),
+        }
+    }
+}
+
+///
+/// `kv`, a single card with its owner.
+///
+pub struct CardView;
+
+impl Parser for CardView {
+    fn name(&self) -> &'static str {
+        "Katana 'Card Details'"
+    }
+    fn source(&self) -> u64 {
+        constants::KATANA_ID
+    }
+    fn detect(&self, message: &MessageData) -> bool {
+        match message.embed() {
+            Some(embed) => {
+                embed.title.as_deref() == Some("Card Details") && embed.description.is_some()
+            }
+            None => false,
+        }
+    }
+    fn parse(&self, message: &MessageData) -> Parsed {
+        let embed = match message.embed() {
+            Some(embed) => embed,
+            None => return Parsed::default(),
+        };
+        let description = match embed.description {
+            Some(ref description) => description,
+            None => return Parsed::default(),
+        };
+        let (card, diagnostics) =
+            utils::parse_card_from_katana_card_view(description, embed.image.as_ref());
+        Parsed {
+            records: card.into_iter().flat_map(card_records).collect(),
+            diagnostics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::EmbedData;
+
+    const KV_DESCRIPTION: &str = "Owned by <@!412279613219520512>
+
+Character · **Frieren**
+Series · **Frieren: Beyond Journey's End**
+Code · `abc12`
+Print · **#123**
+Edition · **◈2**
+Wishlisted · **1,234**";
+
+    fn card_view(description: &str) -> MessageData {
+        MessageData {
+            author_id: constants::KATANA_ID,
+            content: String::new(),
+            embeds: vec![EmbedData {
+                title: Some("Card Details".to_string()),
+                description: Some(description.to_string()),
+                image: Some(
+                    "https://cdn.discordapp.com/attachments/1203/1207/card.png?ex=65f1&is=65de&hm=9a1c"
+                        .to_string(),
+                ),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn card_view_records() {
+        let message = card_view(KV_DESCRIPTION);
+        assert!(CardView.detect(&message));
+        let parsed = CardView.parse(&message);
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(parsed.records.len(), 2);
+        match &parsed.records[0] {
+            Record::Character(character) => {
+                assert_eq!(character.name, "Frieren");
+                assert_eq!(character.series, "Frieren: Beyond Journey's End");
+                assert_eq!(character.wishlist, Some(1234));
+            }
+            record => panic!("Expected a character, got {:?}", record),
+        }
+        match &parsed.records[1] {
+            Record::Card(card) => {
+                assert_eq!(card.code, "abc12");
+                assert_eq!(card.print, Some(123));
+                assert_eq!(card.edition, Some(2));
+                assert_eq!(card.owner, Some(412279613219520512));
+                assert_eq!(
+                    card.image.as_deref(),
+                    Some("https://cdn.discordapp.com/attachments/1203/1207/card.png")
+                );
+            }
+            record => panic!("Expected a card, got {:?}", record),
+        }
+    }
+
+    #[test]
+    fn card_view_without_code() {
+        let message = card_view(&KV_DESCRIPTION.replace("Code · `abc12`\n", ""));
+        let parsed = CardView.parse(&message);
+        assert!(parsed.records.is_empty());
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].field, "code");
+    }
+
+    #[test]
+    fn card_view_with_invalid_code() {
+        let message = card_view(&KV_DESCRIPTION.replace("`abc12`", "`abc 12`"));
+        let parsed = CardView.parse(&message);
+        assert!(parsed.records.is_empty());
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].field, "code");
+    }
+
+    #[test]
+    fn card_view_without_character() {
+        let message = card_view(&KV_DESCRIPTION.replace("Character · **Frieren**\n", ""));
+        let parsed = CardView.parse(&message);
+        assert!(parsed.records.is_empty());
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].field, "name");
+    }
+}
@@ -0,0 +1,40 @@
+use crate::parsers::Parsed;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+///
+/// Counters of a parser since startup.
+///
+#[derive(Debug, Clone, Default)]
+pub struct ParserMetrics {
+    pub messages: u64,
+    pub records: u64,
+    pub diagnostics: u64,
+    // Messages with at least one diagnostic
+    pub failed_messages: u64,
+}
+
+static METRICS: Mutex<BTreeMap<&'static str, ParserMetrics>> = Mutex::new(BTreeMap::new());
+
+pub fn record(parser: &'static str, parsed: &Parsed) {
+    let mut metrics = METRICS.lock().unwrap();
+    let entry = metrics.entry(parser).or_default();
+    entry.messages += 1;
+    entry.records += parsed.records.len() as u64;
+    entry.diagnostics += parsed.diagnostics.len() as u64;
+    if !parsed.diagnostics.is_empty() {
+        entry.failed_messages += 1;
+    }
+}
+
+///
+/// The counters of every parser which handled a message, by parser name.
+///
+pub fn snapshot() -> Vec<(&'static str, ParserMetrics)> {
+    METRICS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, metrics)| (*name, metrics.clone()))
+        .collect()
+}
@@ -0,0 +1,297 @@
+use crate::database;
+use crate::structs::{Card, Character};
+use std::fmt;
+use std::sync::OnceLock;
+use tracing::{debug, error, trace, warn};
+
+pub mod calf;
+pub mod katana;
+pub mod metrics;
+pub mod qingque;
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+///
+/// The parts of a Discord embed parsers read, so they don't depend on a serenity version.
+///
+#[derive(Debug, Clone, Default)]
+pub struct EmbedData {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    // (name, value)
+    pub fields: Vec<(String, String)>,
+    pub footer: Option<String>,
+    pub image: Option<String>,
+}
+
+///
+/// A message (or message update) sent by another bot.
+///
+#[derive(Debug, Clone, Default)]
+pub struct MessageData {
+    pub author_id: u64,
+    pub content: String,
+    pub embeds: Vec<EmbedData>,
+}
+
+impl MessageData {
+    pub fn embed(&self) -> Option<&EmbedData> {
+        self.embeds.first()
+    }
+}
+
+///
+/// Define `embed_data`, `message_data` and `message_update_data`, converting serenity
+/// messages to the form used by the parsers.
+///
+/// The bot and the selfbot use different serenity crates, so the conversions are expanded
+/// in each of them against their own `serenity`.
+///
+#[macro_export]
+macro_rules! message_data_conversions {
+    ($vis:vis) => {
+        ///
+        /// The embed in the form used by the parsers.
+        ///
+        $vis fn embed_data(embed: &serenity::all::Embed) -> $crate::parsers::EmbedData {
+            $crate::parsers::EmbedData {
+                title: embed.title.clone(),
+                author: embed.author.as_ref().map(|author| author.name.clone()),
+                description: embed.description.clone(),
+                fields: embed
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.value.clone()))
+                    .collect(),
+                footer: embed.footer.as_ref().map(|footer| footer.text.clone()),
+                image: embed.image.as_ref().map(|image| image.url.clone()),
+            }
+        }
+
+        ///
+        /// The message in the form used by the parsers.
+        ///
+        $vis fn message_data(msg: &serenity::all::Message) -> $crate::parsers::MessageData {
+            $crate::parsers::MessageData {
+                author_id: msg.author.id.get(),
+                content: msg.content.clone(),
+                embeds: msg.embeds.iter().map(embed_data).collect(),
+            }
+        }
+
+        ///
+        /// The edited message in the form used by the parsers, missing fields are left empty.
+        ///
+        $vis fn message_update_data(
+            event: &serenity::all::MessageUpdateEvent,
+        ) -> $crate::parsers::MessageData {
+            $crate::parsers::MessageData {
+                author_id: match event.author {
+                    Some(ref author) => author.id.get(),
+                    None => 0,
+                },
+                content: event.content.clone().unwrap_or_default(),
+                embeds: match event.embeds {
+                    Some(ref embeds) => embeds.iter().map(embed_data).collect(),
+                    None => Vec::new(),
+                },
+            }
+        }
+    };
+}
+
+///
+/// Information parsed from a message.
+///
+#[derive(Debug, Clone)]
+pub enum Record {
+    Character(Character),
+    Card(Card),
+}
+
+///
+/// Why a line of a message couldn't be parsed.
+///
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    // 1-based, 0 if the problem isn't on a specific line
+    pub line: usize,
+    pub raw: String,
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl Diagnostic {
+    pub fn new(
+        line: usize,
+        raw: &str,
+        field: &'static str,
+        reason: impl Into<String>,
+    ) -> Diagnostic {
+        Diagnostic {
+            line,
+            raw: raw.to_string(),
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "Failed to parse {}: {}", self.field, self.reason),
+            line => write!(
+                f,
+                "Line {}: failed to parse {}: {} (`{}`)",
+                line, self.field, self.reason, self.raw
+            ),
+        }
+    }
+}
+
+///
+/// The records parsed from a message, and the lines which couldn't be.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Parsed {
+    pub records: Vec<Record>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Parsed {
+    pub fn characters(cards: Vec<Character>, diagnostics: Vec<Diagnostic>) -> Parsed {
+        Parsed {
+            records: cards.into_iter().map(Record::Character).collect(),
+            diagnostics,
+        }
+    }
+}
+
+///
+/// Reads one message format of a bot into records.
+///
+pub trait Parser: Send + Sync {
+    ///
+    /// Name of the format, e.g. "Katana 'Card Collection'".
+    ///
+    fn name(&self) -> &'static str;
+    ///
+    /// ID of the bot sending the messages.
+    ///
+    fn source(&self) -> u64;
+    ///
+    /// Whether the message is in this format.
+    ///
+    fn detect(&self, message: &MessageData) -> bool;
+    fn parse(&self, message: &MessageData) -> Parsed;
+}
+
+///
+/// Dispatches messages to the parser of their format.
+///
+#[derive(Default)]
+pub struct Registry {
+    parsers: Vec<Box<dyn Parser>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            parsers: Vec::new(),
+        }
+    }
+    ///
+    /// A registry with all the formats supported by Swordfish.
+    ///
+    pub fn builtin() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(katana::CardCollection);
+        registry.register(katana::CharacterLookup);
+        registry.register(katana::CharacterResults);
+        registry.register(katana::CardView);
+        registry.register(qingque::TopWishlist);
+        registry.register(calf::DropAnalysis);
+        registry
+    }
+    pub fn register(&mut self, parser: impl Parser + 'static) {
+        self.parsers.push(Box::new(parser));
+    }
+    pub fn parsers(&self) -> impl Iterator<Item = &dyn Parser> {
+        self.parsers.iter().map(|parser| parser.as_ref())
+    }
+    ///
+    /// The parser of the message's format, if any.
+    ///
+    pub fn find(&self, message: &MessageData) -> Option<&dyn Parser> {
+        self.parsers()
+            .find(|parser| parser.source() == message.author_id && parser.detect(message))
+    }
+    ///
+    /// Parse the message and write its records to the database.
+    ///
+    pub async fn dispatch(&self, message: &MessageData) {
+        let parser = match self.find(message) {
+            Some(parser) => parser,
+            None => {
+                trace!("No parser for message from {}", message.author_id);
+                return;
+            }
+        };
+        let parsed = parser.parse(message);
+        metrics::record(parser.name(), &parsed);
+        if !parsed.diagnostics.is_empty() {
+            warn!(
+                "{} lines of {} couldn't be parsed, did the format change?",
+                parsed.diagnostics.len(),
+                parser.name()
+            );
+        }
+        if parsed.records.is_empty() {
+            return;
+        }
+        debug!(
+            "Importing {} records from {}",
+            parsed.records.len(),
+            parser.name()
+        );
+        match write_records(parsed.records).await {
+            Ok(_) => debug!("Imported successfully"),
+            Err(why) => error!("Failed to import records: {:?}", why),
+        }
+    }
+}
+
+async fn write_records(records: Vec<Record>) -> Result<(), String> {
+    let mut characters: Vec<Character> = Vec::new();
+    let mut unknown_wishlist: Vec<Character> = Vec::new();
+    let mut cards: Vec<Card> = Vec::new();
+    for record in records {
+        match record {
+            Record::Character(character) if character.wishlist.is_none() => {
+                unknown_wishlist.push(character)
+            }
+            Record::Character(character) => characters.push(character),
+            Record::Card(card) => cards.push(card),
+        }
+    }
+    for card in cards {
+        database::katana::write_card(card).await?;
+    }
+    // Their wishlist isn't shown, so only add them if they're unknown
+    if !unknown_wishlist.is_empty() {
+        database::katana::insert_missing_characters(unknown_wishlist).await?;
+    }
+    if characters.is_empty() {
+        return Ok(());
+    }
+    database::katana::write_characters(characters).await
+}
+
+///
+/// The registry with the built-in parsers.
+///
+pub fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(Registry::builtin)
+}
@@ -0,0 +1,34 @@
+use crate::constants;
+use crate::parsers::{MessageData, Parsed, Parser};
+use crate::utils::katana as utils;
+
+///
+/// `atopwl`, the most wishlisted Katana characters.
+///
+pub struct TopWishlist;
+
+impl Parser for TopWishlist {
+    fn name(&self) -> &'static str {
+        "Qingque 'Top Wishlist'"
+    }
+    fn source(&self) -> u64 {
+        constants::QINGQUE_ID
+    }
+    fn detect(&self, message: &MessageData) -> bool {
+        match message.embed() {
+            Some(embed) => {
+                embed.title.as_deref() == Some("Top Wishlist") && embed.description.is_some()
+            }
+            None => false,
+        }
+    }
+    fn parse(&self, message: &MessageData) -> Parsed {
+        match message.embed().and_then(|embed| embed.description.as_ref()) {
+            Some(description) => {
+                let (cards, diagnostics) = utils::parse_cards_from_qingque_atopwl(description);
+                Parsed::characters(cards, diagnostics)
+            }
+            None => Parsed::default(),
+        }
+    }
+}
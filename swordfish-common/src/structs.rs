@@ -8,9 +8,75 @@ pub struct Character {
     pub last_update_ts: i64,
 }
 
+///
+/// A single card, as shown when viewing it or in a collection.
+///
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Card {
+    pub code: String,
+    pub name: String,
+    pub series: String,
+    // Emoji the owner tagged the card with
+    pub tag: Option<String>,
+    pub print: Option<u32>,
+    pub edition: Option<u32>,
+    // Discord user ID
+    pub owner: Option<u64>,
+    pub wishlist: Option<u32>,
+    // URL of the card image
+    pub image: Option<String>,
+    pub last_update_ts: i64,
+}
+
+///
+/// Perceptual hash of a card's art, mapped to the character it was identified as.
+///
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Fingerprint {
+    pub hash: i64,
+    pub name: String,
+    pub series: String,
+    // Times the art was identified as this character.
+    pub count: u32,
+    pub last_update_ts: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WordConfidence {
+    pub text: String,
+    pub confidence: f32,
+}
+
+///
+/// Tesseract confidence (0 - 100) of a text region.
+///
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OcrConfidence {
+    pub mean: f32,
+    pub words: Vec<WordConfidence>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum MatchKind {
+    Exact,
+    Regex,
+    // Matched the series, then the name among the series' characters.
+    SeriesFirst,
+    // Matched the name, then the series among the characters with that name.
+    NameFirst,
+    // Matched the card art with a previously identified card.
+    Fingerprint,
+    NotFound,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DroppedCard {
     pub character: Character,
     pub print: i32,
     pub edition: i32,
+    pub name_confidence: OcrConfidence,
+    pub series_confidence: OcrConfidence,
+    pub match_kind: MatchKind,
+    // Similarity (0 - 1) between the OCR text and the matched character.
+    pub match_similarity: f32,
 }
@@ -1,44 +1,101 @@
-use crate::structs::Character;
-use log::{error, trace};
+use crate::parsers::Diagnostic;
+use crate::structs::{Card, Character};
+use log::{trace, warn};
+
+///
+/// Parse a count (e.g. wishlists), ignoring thousands separators and whitespace.
+///
+fn parse_number(number: &str) -> Result<u32, String> {
+    let number_string: String = number
+        .chars()
+        .filter(|c| *c != ',' && !c.is_whitespace())
+        .collect();
+    trace!("Formatted number: {}", number_string);
+    match number_string.parse::<u32>() {
+        Ok(number) => Ok(number),
+        Err(why) => Err(format!("`{}` is not a number: {}", number_string, why)),
+    }
+}
+
+///
+/// The user ID of the first user mention (`<@id>` or `<@!id>`) in the line.
+///
+fn parse_mention(line: &str) -> Result<u64, String> {
+    let start = match line.find("<@") {
+        Some(start) => start + 2,
+        None => return Err("no user mention".to_string()),
+    };
+    let mention = line[start..].trim_start_matches('!');
+    let id = match mention.split_once('>') {
+        Some((id, _)) => id,
+        None => return Err("unterminated user mention".to_string()),
+    };
+    match id.parse::<u64>() {
+        Ok(id) => Ok(id),
+        Err(why) => Err(format!("`{}` is not a user ID: {}", id, why)),
+    }
+}
+
+///
+/// Whether a line which couldn't be parsed looks like a record, with a code block or
+/// ` · ` separated columns.
+///
+/// Headers, footers and separators don't, and aren't worth a diagnostic.
+///
+fn is_record_like(line: &str) -> bool {
+    line.contains('`') || line.contains(" · ")
+}
+
+fn diagnose(diagnostics: &mut Vec<Diagnostic>, diagnostic: Diagnostic) {
+    warn!("{}", diagnostic);
+    diagnostics.push(diagnostic);
+}
 
 // atopwl
-pub fn parse_cards_from_qingque_atopwl(content: &String) -> Vec<Character> {
+pub fn parse_cards_from_qingque_atopwl(content: &String) -> (Vec<Character>, Vec<Diagnostic>) {
     let mut cards: Vec<Character> = Vec::new();
-    for line in content.split("\n") {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for (i, line) in content.split("\n").enumerate() {
         trace!("Parsing line: {}", line);
+        if line.trim().is_empty() {
+            continue;
+        }
         let mut line_split = line.split(" · ");
         let wishlist = match line_split.nth(1) {
-            Some(wishlist_str) => {
-                let mut wl_string = wishlist_str.to_string();
-                // Remove `
-                wl_string.remove(0);
-                // Remove ❤ (Double because heart is 2 bytes)
-                wl_string.remove(0);
-                wl_string.remove(0);
-                // Remove last ``
-                wl_string.pop();
-                // Remove "," in the number
-                wl_string.remove_matches(",");
-                // Remove whitespace
-                wl_string = wl_string
-                    .split_whitespace()
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                trace!("Formatted wishlist number:{}", wl_string);
-                match wl_string.parse::<u32>() {
-                    Ok(wishlist) => wishlist,
-                    Err(_) => {
-                        error!("Failed to parse wishlist number: {}", wishlist_str);
-                        continue;
-                    }
+            // `❤ 1,234`
+            Some(wishlist_str) => match parse_number(
+                wishlist_str
+                    .trim_matches('`')
+                    .trim_start_matches(['❤', '\u{fe0f}']),
+            ) {
+                Ok(wishlist) => wishlist,
+                Err(why) => {
+                    diagnose(
+                        &mut diagnostics,
+                        Diagnostic::new(i + 1, line, "wishlist", why),
+                    );
+                    continue;
+                }
+            },
+            None => {
+                if is_record_like(line) {
+                    diagnose(
+                        &mut diagnostics,
+                        Diagnostic::new(i + 1, line, "wishlist", "missing ` · ` separator"),
+                    );
                 }
+                continue;
             }
-            None => continue,
         };
         let series = match line_split.next() {
             Some(series) => series.to_string(),
-            None => continue,
+            None => {
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "series", "missing after the wishlist"),
+                );
+                continue;
+            }
         };
         let name = match line_split.next() {
             Some(name) => {
@@ -46,7 +103,13 @@ pub fn parse_cards_from_qingque_atopwl(content: &String) -> Vec<Character> {
                 name_string.remove_matches("**");
                 name_string
             }
-            None => continue,
+            None => {
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "name", "missing after the series"),
+                );
+                continue;
+            }
         };
         let card = Character {
             wishlist: Some(wishlist),
@@ -57,63 +120,174 @@ pub fn parse_cards_from_qingque_atopwl(content: &String) -> Vec<Character> {
         trace!("Parsed card: {:?}", card);
         cards.push(card);
     }
-    cards
+    (cards, diagnostics)
 }
 
-// kc o:w
-pub fn parse_cards_from_katana_kc_ow(content: &String) -> Vec<Character> {
-    let mut cards: Vec<Character> = Vec::new();
-    for line in content.split("\n") {
-        let mut line = line.to_string();
+///
+/// Whether the text is a card code, 4 to 8 ASCII letters and digits such as "abc12".
+///
+fn is_code(code: &str) -> bool {
+    (4..=8).contains(&code.len()) && code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+///
+/// The card code in a collection column, a code block such as "`abc12`".
+///
+fn collection_code(column: &str) -> Option<&str> {
+    let code = column.trim().strip_prefix('`')?.strip_suffix('`')?;
+    match is_code(code) {
+        true => Some(code),
+        false => None,
+    }
+}
+
+///
+/// Whether the collection column starts with the prefix of a card field: the wishlist (`♡`),
+/// the print (`#`) or the edition (`◈`).
+///
+fn has_field_prefix(column: &str) -> bool {
+    column
+        .trim_start_matches(|c: char| c == '`' || c == '*' || c.is_whitespace())
+        .starts_with(['♡', '#', '◈'])
+}
+
+///
+/// The index of the code among the card columns of a collection line, by the sort order.
+///
+/// The sort column comes first and the code right after it, unless the cards are sorted by
+/// code. The sort column is then the code itself and is followed by the print.
+///
+fn code_index<'a>(columns: &[&'a str]) -> Option<(usize, &'a str)> {
+    let index = match columns.get(1) {
+        Some(next) if !has_field_prefix(columns[0]) && has_field_prefix(next) => 0,
+        Some(_) => 1,
+        None => 0,
+    };
+    collection_code(columns[index]).map(|code| (index, code))
+}
+
+///
+/// Fill the card field a collection column holds, telling them apart by their prefix.
+///
+/// Columns which aren't a card field (e.g. dates) are ignored.
+///
+fn parse_collection_column(
+    column: &str,
+    card: &mut Card,
+    location: (usize, &str),
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut column_string = column.to_string();
+    column_string.remove_matches("`");
+    column_string.remove_matches("**");
+    let column = column_string.trim();
+    let (field, number) = if let Some(wishlist) = column.strip_prefix('♡') {
+        ("wishlist", wishlist)
+    } else if let Some(print) = column.strip_prefix('#') {
+        ("print", print)
+    } else if let Some(edition) = column.strip_prefix('◈') {
+        ("edition", edition)
+    } else {
+        return;
+    };
+    match parse_number(number) {
+        Ok(number) => match field {
+            "wishlist" => card.wishlist = Some(number),
+            "print" => card.print = Some(number),
+            _ => card.edition = Some(number),
+        },
+        Err(why) => diagnose(
+            diagnostics,
+            Diagnostic::new(location.0, location.1, field, why),
+        ),
+    }
+}
+
+// kc
+///
+/// Given the description of a katana collection page, parse it into a vector of cards.
+///
+/// Every sort order is supported, lines are formatted as
+/// "<tag> `<sort column>` · <columns...> · <series> · **<name>**" where the columns are
+/// the code, print (`#`), edition (`◈`) and wishlist (`♡`). The code comes first, right
+/// after the sort column unless it's the sort column itself (see `code_index`), the other
+/// columns are told apart by their prefix. Cards whose code isn't shown have an empty code.
+///
+pub fn parse_cards_from_katana_kc(content: &String) -> (Vec<Card>, Vec<Diagnostic>) {
+    let mut cards: Vec<Card> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut owner: Option<u64> = None;
+    for (i, raw_line) in content.split("\n").enumerate() {
+        let mut line = raw_line.to_string();
         line.remove_matches("~~");
         trace!("Parsing line: {}", line);
         if !line.ends_with("**") {
-            continue;
-        }
-        let mut line_split = line.split(" · ");
-        let tag_wl_block = line_split.nth(0).unwrap();
-        let mut wl_block = match tag_wl_block.split("`").nth(1) {
-            Some(wl_block) => {
-                // If one does not start with ♡, it is not a wishlist command
-                // then we'll just break entirely.
-                if !wl_block.starts_with("♡") {
-                    break;
+            // "Cards carried by <@123456789>"
+            if line.contains("<@") {
+                match parse_mention(&line) {
+                    Ok(id) => owner = Some(id),
+                    Err(why) => diagnose(
+                        &mut diagnostics,
+                        Diagnostic::new(i + 1, raw_line, "owner", why),
+                    ),
                 }
-                wl_block.to_string()
             }
-            None => break,
+            continue;
+        }
+        let columns: Vec<&str> = line.split(" · ").collect();
+        if columns.len() < 3 {
+            diagnose(
+                &mut diagnostics,
+                Diagnostic::new(
+                    i + 1,
+                    raw_line,
+                    "series",
+                    "expected at least 3 ` · ` separated columns",
+                ),
+            );
+            continue;
+        }
+        let mut name = columns[columns.len() - 1].to_string();
+        name.remove_matches("**");
+        let mut card = Card {
+            code: String::new(),
+            name: name.trim().to_string(),
+            series: columns[columns.len() - 2].trim().to_string(),
+            tag: None,
+            print: None,
+            edition: None,
+            owner,
+            wishlist: None,
+            image: None,
+            last_update_ts: 0,
         };
-        wl_block.remove(0);
-        wl_block = wl_block.trim().to_string();
-        let wishlist = match wl_block.parse::<u32>() {
-            Ok(wishlist) => wishlist,
-            Err(_) => {
-                error!("Failed to parse wishlist number: {}", wl_block);
-                continue;
+        // The tag (if any) is before the sort column
+        let first = match columns[0].find('`') {
+            Some(start) => {
+                let tag = columns[0][..start].trim();
+                if !tag.is_empty() {
+                    card.tag = Some(tag.to_string());
+                }
+                &columns[0][start..]
             }
+            None => columns[0],
         };
-        let series = match line_split.nth(4) {
-            Some(series) => series.to_string(),
-            None => continue,
-        };
-        let name = match line_split.next() {
-            Some(name) => {
-                let mut name_string = name.to_string();
-                name_string.remove_matches("**");
-                name_string
+        let location = (i + 1, raw_line);
+        let mut card_columns: Vec<&str> = vec![first];
+        card_columns.extend_from_slice(&columns[1..columns.len() - 2]);
+        let code = code_index(&card_columns);
+        if let Some((_, code)) = code {
+            card.code = code.to_string();
+        }
+        for (j, column) in card_columns.iter().enumerate() {
+            if code.map(|(index, _)| index) != Some(j) {
+                parse_collection_column(column, &mut card, location, &mut diagnostics);
             }
-            None => continue,
-        };
-        let card = Character {
-            wishlist: Some(wishlist),
-            name,
-            series,
-            last_update_ts: 0,
-        };
+        }
         trace!("Parsed card: {:?}", card);
         cards.push(card);
     }
-    cards
+    (cards, diagnostics)
 }
 
 // klu (Character Results)
@@ -122,29 +296,47 @@ pub fn parse_cards_from_katana_kc_ow(content: &String) -> Vec<Character> {
 ///
 /// "content" is `fields[0].value`
 ///
-pub fn parse_cards_from_katana_klu_results(content: &String) -> Vec<Character> {
+pub fn parse_cards_from_katana_klu_results(content: &String) -> (Vec<Character>, Vec<Diagnostic>) {
     let mut cards: Vec<Character> = Vec::new();
-    for line in content.split("\n") {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for (i, line) in content.split("\n").enumerate() {
         trace!("Parsing line: {}", line);
+        if line.trim().is_empty() {
+            continue;
+        }
         if !line.ends_with("**") {
+            if is_record_like(line) {
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(
+                        i + 1,
+                        line,
+                        "record",
+                        "unrecognized line, no bold name at the end",
+                    ),
+                );
+            }
             continue;
         }
         // Split into ['', '1', '. ', '♡448', " · Frieren: Beyond Journey's End · **Frieren**"]
         // But we need 4th and 5th ones.
         let mut line_split = line.split("`");
-        let mut wl_str = match line_split.nth(3) {
-            Some(wl_str) => wl_str.to_string(),
+        let wishlist = match line_split.nth(3) {
+            Some(wl_str) => match parse_number(wl_str.trim_start_matches('♡')) {
+                Ok(wishlist) => wishlist,
+                Err(why) => {
+                    diagnose(
+                        &mut diagnostics,
+                        Diagnostic::new(i + 1, line, "wishlist", why),
+                    );
+                    continue;
+                }
+            },
             None => {
-                error!("Failed to parse wishlist number: {}", line);
-                continue;
-            }
-        };
-        wl_str.remove(0);
-        wl_str = wl_str.trim().to_string();
-        let wishlist = match wl_str.parse::<u32>() {
-            Ok(wishlist) => wishlist,
-            Err(_) => {
-                error!("Failed to parse wishlist number: {}", wl_str);
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "wishlist", "missing the `♡` block"),
+                );
                 continue;
             }
         };
@@ -152,14 +344,20 @@ pub fn parse_cards_from_katana_klu_results(content: &String) -> Vec<Character> {
         let mut char_series_split = match line_split.next() {
             Some(char_series_split) => char_series_split.split(" · "),
             None => {
-                error!("Failed to parse character name and series: {}", line);
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "series", "missing after the wishlist"),
+                );
                 continue;
             }
         };
         let series = match char_series_split.nth(1) {
             Some(series) => series.to_string(),
             None => {
-                error!("Failed to parse series: {}", line);
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "series", "missing ` · ` separator"),
+                );
                 continue;
             }
         };
@@ -170,7 +368,10 @@ pub fn parse_cards_from_katana_klu_results(content: &String) -> Vec<Character> {
                 name_string
             }
             None => {
-                error!("Failed to parse character name: {}", line);
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "name", "missing after the series"),
+                );
                 continue;
             }
         };
@@ -183,110 +384,259 @@ pub fn parse_cards_from_katana_klu_results(content: &String) -> Vec<Character> {
         trace!("Parsed card: {:?}", card);
         cards.push(card);
     }
-    cards
+    (cards, diagnostics)
 }
 
 // klu (Character Lookup)
-pub fn parse_cards_from_katana_klu_lookup(content: &String) -> Option<Character> {
-    let mut lines = content.split("\n");
+pub fn parse_cards_from_katana_klu_lookup(
+    content: &String,
+) -> (Option<Character>, Vec<Diagnostic>) {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut lines = content.split("\n").enumerate();
     // Character
-    let mut line_split = lines.nth(0).unwrap().split(" · ");
-    let name = match line_split.nth(1) {
+    let (i, line) = lines.next().unwrap();
+    let name = match line.split(" · ").nth(1) {
         Some(name) => {
             let mut name_string = name.to_string();
             name_string.remove_matches("**");
             name_string
         }
-        None => return None,
-    };
-    // Series
-    let mut line_split = lines.nth(0).unwrap().split(" · ");
-    let series = match line_split.nth(1) {
-        Some(series) => {
-            let mut series_string = series.to_string();
-            series_string.remove_matches("**");
-            series_string
+        None => {
+            diagnose(
+                &mut diagnostics,
+                Diagnostic::new(i + 1, line, "name", "missing ` · ` separator"),
+            );
+            return (None, diagnostics);
         }
-        None => return None,
     };
-    // Wishlist
-    let mut line_split: Option<std::str::Split<'_, &str>> = None;
-    while line_split.is_none() {
-        line_split = match lines.next() {
-            Some(line) => {
-                if line.contains("Wishlisted") {
-                    Some(line.split(" · "))
-                } else {
-                    None
-                }
+    // Series
+    let series = match lines.next() {
+        Some((i, line)) => match line.split(" · ").nth(1) {
+            Some(series) => {
+                let mut series_string = series.to_string();
+                series_string.remove_matches("**");
+                series_string
             }
             None => {
-                error!("Failed to parse wishlist number: {}", content);
-                return None;
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "series", "missing ` · ` separator"),
+                );
+                return (None, diagnostics);
             }
+        },
+        None => {
+            diagnose(
+                &mut diagnostics,
+                Diagnostic::new(0, "", "series", "missing the series line"),
+            );
+            return (None, diagnostics);
         }
-    }
-    let wishlist = match line_split?.nth(1) {
+    };
+    // Wishlist
+    let (i, line) = match lines.find(|(_, line)| line.contains("Wishlisted")) {
+        Some(line) => line,
+        None => {
+            diagnose(
+                &mut diagnostics,
+                Diagnostic::new(0, "", "wishlist", "missing the `Wishlisted` line"),
+            );
+            return (None, diagnostics);
+        }
+    };
+    let wishlist = match line.split(" · ").nth(1) {
         Some(wl) => {
             let mut wl_string = wl.to_string();
             wl_string.remove_matches("**");
-            wl_string.remove_matches(",");
-            match wl_string.parse::<u32>() {
+            match parse_number(&wl_string) {
                 Ok(wishlist) => wishlist,
-                Err(_) => {
-                    error!("Failed to parse wishlist number: {}", wl_string);
-                    return None;
+                Err(why) => {
+                    diagnose(
+                        &mut diagnostics,
+                        Diagnostic::new(i + 1, line, "wishlist", why),
+                    );
+                    return (None, diagnostics);
                 }
             }
         }
-        None => return None,
+        None => {
+            diagnose(
+                &mut diagnostics,
+                Diagnostic::new(i + 1, line, "wishlist", "missing ` · ` separator"),
+            );
+            return (None, diagnostics);
+        }
     };
-    Some(Character {
+    let card = Character {
         wishlist: Some(wishlist),
         name,
         series,
         last_update_ts: 0,
-    })
+    };
+    (Some(card), diagnostics)
+}
+
+///
+/// The image URL without its query string.
+///
+/// Discord signs CDN URLs with an expiry in the query, the path stays the same and
+/// identifies the image.
+///
+fn stable_image_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((path, _)) => path.to_string(),
+        None => url.to_string(),
+    }
+}
+
+// kv (Card Details)
+///
+/// Given the description of a katana card view, parse the card and its character.
+///
+/// A card view (`kv`) embed looks like this, the description being "content":
+///
+/// ```text
+/// Title:       Card Details
+/// Description: Owned by <@!412279613219520512>
+///
+///              Character · **Frieren**
+///              Series · **Frieren: Beyond Journey's End**
+///              Code · `abc12`
+///              Print · **#123**
+///              Edition · **◈2**
+///              Wishlisted · **1,234**
+/// Image:       https://cdn.discordapp.com/attachments/1203/1207/card.png?ex=65f1&is=65de&hm=9a1c
+/// ```
+///
+/// The `Wishlisted` line isn't always present.
+///
+/// "image" is the URL of the embed image, the card itself. It's stored without the query
+/// string, e.g. `https://cdn.discordapp.com/attachments/1/2/card.png?ex=65f1&is=65de&hm=9a1c`
+/// is stored as `https://cdn.discordapp.com/attachments/1/2/card.png`.
+///
+pub fn parse_card_from_katana_card_view(
+    content: &String,
+    image: Option<&String>,
+) -> (Option<Card>, Vec<Diagnostic>) {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut card = Card {
+        code: String::new(),
+        name: String::new(),
+        series: String::new(),
+        tag: None,
+        print: None,
+        edition: None,
+        owner: None,
+        wishlist: None,
+        image: image.map(|image| stable_image_url(image)),
+        last_update_ts: 0,
+    };
+    for (i, line) in content.split("\n").enumerate() {
+        trace!("Parsing line: {}", line);
+        if line.starts_with("Owned by") {
+            match parse_mention(line) {
+                Ok(owner) => card.owner = Some(owner),
+                Err(why) => diagnose(&mut diagnostics, Diagnostic::new(i + 1, line, "owner", why)),
+            }
+            continue;
+        }
+        let (key, value) = match line.split_once(" · ") {
+            Some((key, value)) => {
+                let mut value_string = value.to_string();
+                value_string.remove_matches("**");
+                value_string.remove_matches("`");
+                (key.trim(), value_string.trim().to_string())
+            }
+            None => continue,
+        };
+        match key {
+            "Character" => card.name = value,
+            "Series" => card.series = value,
+            "Code" => match is_code(&value) {
+                true => card.code = value,
+                false => diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "code", format!("`{}` is not a code", value)),
+                ),
+            },
+            "Print" => match parse_number(value.trim_start_matches('#')) {
+                Ok(print) => card.print = Some(print),
+                Err(why) => diagnose(&mut diagnostics, Diagnostic::new(i + 1, line, "print", why)),
+            },
+            "Edition" => match parse_number(value.trim_start_matches('◈')) {
+                Ok(edition) => card.edition = Some(edition),
+                Err(why) => diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "edition", why),
+                ),
+            },
+            "Wishlisted" => match parse_number(&value) {
+                Ok(wishlist) => card.wishlist = Some(wishlist),
+                Err(why) => diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "wishlist", why),
+                ),
+            },
+            _ => {}
+        }
+    }
+    for (field, value) in [
+        ("code", &card.code),
+        ("name", &card.name),
+        ("series", &card.series),
+    ] {
+        // A field which couldn't be parsed is already reported
+        if value.is_empty() && !diagnostics.iter().any(|d| d.field == field) {
+            diagnose(
+                &mut diagnostics,
+                Diagnostic::new(0, "", field, "missing from the card view"),
+            );
+        }
+    }
+    if card.code.is_empty() || card.name.is_empty() || card.series.is_empty() {
+        return (None, diagnostics);
+    }
+    trace!("Parsed card: {:?}", card);
+    (Some(card), diagnostics)
 }
 
-pub fn parse_cards_from_calf_analysis(content: &String) -> Vec<Character> {
+pub fn parse_cards_from_calf_analysis(content: &String) -> (Vec<Character>, Vec<Diagnostic>) {
     let mut cards: Vec<Character> = Vec::new();
-    let mut lines = content.split("\n");
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
     // Skip first 2 lines
-    lines.nth(1);
-    for line in lines {
+    for (i, line) in content.split("\n").enumerate().skip(2) {
         trace!("Parsing line: {}", line);
         let mut line_string = line.to_string();
         // Remove first `
         match line.find('`') {
-            Some(i) => {
-                line_string.drain(0..i + 1);
+            Some(start) => {
+                line_string.drain(0..start + 1);
+            }
+            None => {
+                if is_record_like(line) {
+                    diagnose(
+                        &mut diagnostics,
+                        Diagnostic::new(i + 1, line, "record", "unrecognized line, no `♡` block"),
+                    );
+                }
+                continue;
             }
-            None => continue,
         }
         let mut line_split = line_string.split('`');
         let wishlist = match line_split.nth(0) {
             Some(wishlist_str) => {
-                let mut wl_string = wishlist_str.to_string();
-                // Remove ♡
-                wl_string.remove(0);
+                let wl_string = wishlist_str.trim_start_matches('♡');
                 if wl_string.starts_with('?') {
                     continue;
                 }
-                // Remove "," in the number
-                // I don't know, I've never seen this myself.
-                wl_string.remove_matches(",");
-                // Remove whitespace
-                wl_string = wl_string
-                    .split_whitespace()
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                trace!("Formatted wishlist number: {}", wl_string);
-                match wl_string.parse::<u32>() {
+                // "," in the number, I don't know, I've never seen this myself.
+                match parse_number(wl_string) {
                     Ok(wishlist) => wishlist,
-                    Err(_) => {
-                        error!("Failed to parse wishlist number: {}", wishlist_str);
+                    Err(why) => {
+                        diagnose(
+                            &mut diagnostics,
+                            Diagnostic::new(i + 1, line, "wishlist", why),
+                        );
                         continue;
                     }
                 }
@@ -295,11 +645,23 @@ pub fn parse_cards_from_calf_analysis(content: &String) -> Vec<Character> {
         };
         let mut name_series_split = match line_split.nth(0) {
             Some(split) => split.split(" · "),
-            None => continue,
+            None => {
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "series", "missing after the wishlist"),
+                );
+                continue;
+            }
         };
         let series = match name_series_split.next() {
             Some(series) => series.trim().to_string(),
-            None => continue,
+            None => {
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "series", "missing after the wishlist"),
+                );
+                continue;
+            }
         };
         let name = match name_series_split.next() {
             Some(name) => {
@@ -317,7 +679,13 @@ pub fn parse_cards_from_calf_analysis(content: &String) -> Vec<Character> {
                 }
                 name_string
             }
-            None => continue,
+            None => {
+                diagnose(
+                    &mut diagnostics,
+                    Diagnostic::new(i + 1, line, "name", "missing ` · ` separator"),
+                );
+                continue;
+            }
         };
         let card = Character {
             wishlist: Some(wishlist),
@@ -328,5 +696,171 @@ pub fn parse_cards_from_calf_analysis(content: &String) -> Vec<Character> {
         trace!("Parsed card: {:?}", card);
         cards.push(card);
     }
-    cards
+    (cards, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERIES: &str = "Frieren: Beyond Journey's End";
+
+    fn parse_kc_line(line: &str) -> Card {
+        let content = format!("Cards carried by <@412279613219520512>\n\n{}", line);
+        let (cards, diagnostics) = parse_cards_from_katana_kc(&content);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(cards.len(), 1);
+        let card = cards.into_iter().next().unwrap();
+        assert_eq!(card.owner, Some(412279613219520512));
+        assert_eq!(card.name, "Frieren");
+        assert_eq!(card.series, SERIES);
+        card
+    }
+
+    #[test]
+    fn collection_codes() {
+        assert_eq!(collection_code("`abc12`"), Some("abc12"));
+        assert_eq!(collection_code(" `a1b2c3d4` "), Some("a1b2c3d4"));
+        assert_eq!(collection_code("`abc`"), None);
+        assert_eq!(collection_code("`a1b2c3d4e`"), None);
+        assert_eq!(collection_code("`#123`"), None);
+        assert_eq!(collection_code("abc12"), None);
+    }
+
+    #[test]
+    fn kc_sorted_by_code() {
+        let card = parse_kc_line(&format!(
+            "`abc12` · `#123` · `◈2` · {} · **Frieren**",
+            SERIES
+        ));
+        assert_eq!(card.code, "abc12");
+        assert_eq!(card.print, Some(123));
+        assert_eq!(card.edition, Some(2));
+        assert_eq!(card.wishlist, None);
+    }
+
+    #[test]
+    fn kc_sorted_by_wishlist() {
+        let card = parse_kc_line(&format!(
+            "🔥 `♡1,234` · `abc12` · `#123` · `◈2` · {} · **Frieren**",
+            SERIES
+        ));
+        assert_eq!(card.tag.as_deref(), Some("🔥"));
+        assert_eq!(card.code, "abc12");
+        assert_eq!(card.wishlist, Some(1234));
+        assert_eq!(card.print, Some(123));
+        assert_eq!(card.edition, Some(2));
+    }
+
+    #[test]
+    fn kc_sorted_by_print() {
+        let card = parse_kc_line(&format!(
+            "`#123` · `abc12` · `◈2` · {} · **Frieren**",
+            SERIES
+        ));
+        assert_eq!(card.code, "abc12");
+        assert_eq!(card.print, Some(123));
+        assert_eq!(card.edition, Some(2));
+    }
+
+    #[test]
+    fn kc_sorted_by_edition() {
+        let card = parse_kc_line(&format!(
+            "~~`◈2` · `abc12` · `#123` · {} · **Frieren**~~",
+            SERIES
+        ));
+        assert_eq!(card.code, "abc12");
+        assert_eq!(card.print, Some(123));
+        assert_eq!(card.edition, Some(2));
+    }
+
+    #[test]
+    fn kc_sorted_by_date() {
+        let card = parse_kc_line(&format!(
+            "`2024-01-31` · `abc12` · `#123` · `◈2` · {} · **Frieren**",
+            SERIES
+        ));
+        assert_eq!(card.code, "abc12");
+        assert_eq!(card.print, Some(123));
+    }
+
+    #[test]
+    fn kc_sorted_by_unprefixed_number() {
+        // A number sort column is also a valid code, the code is still the next column
+        let card = parse_kc_line(&format!(
+            "`12345` · `abc12` · `#123` · `◈2` · {} · **Frieren**",
+            SERIES
+        ));
+        assert_eq!(card.code, "abc12");
+        assert_eq!(card.print, Some(123));
+    }
+
+    #[test]
+    fn kc_without_code() {
+        let card = parse_kc_line(&format!("`♡12` · `#123` · {} · **Frieren**", SERIES));
+        assert_eq!(card.code, "");
+        assert_eq!(card.wishlist, Some(12));
+        assert_eq!(card.print, Some(123));
+    }
+
+    #[test]
+    fn klu_results() {
+        let content = format!(
+            "`1`. `♡448` · {} · **Frieren**\n`2`. `♡12` · {} · **Fern**",
+            SERIES, SERIES
+        );
+        let (cards, diagnostics) = parse_cards_from_katana_klu_results(&content);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].name, "Frieren");
+        assert_eq!(cards[0].series, SERIES);
+        assert_eq!(cards[0].wishlist, Some(448));
+        assert_eq!(cards[1].name, "Fern");
+        assert_eq!(cards[1].wishlist, Some(12));
+    }
+
+    #[test]
+    fn klu_results_only_diagnoses_records() {
+        let content = format!(
+            "Showing 1-2 of 2\n`1`. `♡448` · {} · **Frieren**\n`2`. `♡12` · {} · Fern\n───",
+            SERIES, SERIES
+        );
+        let (cards, diagnostics) = parse_cards_from_katana_klu_results(&content);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].field, "record");
+    }
+
+    #[test]
+    fn calf_analysis() {
+        let content = format!(
+            "**Analysis**\n\n`♡448` {} · **Frieren** (3)\n`♡1,234` {} · **Fern** [SINGLE-PRINT]\n`♡?` {} · **Stark**\nPage 1/1",
+            SERIES, SERIES, SERIES
+        );
+        let (cards, diagnostics) = parse_cards_from_calf_analysis(&content);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].name, "Frieren");
+        assert_eq!(cards[0].series, SERIES);
+        assert_eq!(cards[0].wishlist, Some(448));
+        assert_eq!(cards[1].name, "Fern");
+        assert_eq!(cards[1].wishlist, Some(1234));
+    }
+
+    #[test]
+    fn qingque_atopwl() {
+        let content = format!(
+            "Top wishlisted\n`1.` · `❤ 1,234` · {} · **Frieren**\n`2.` · `❤ x` · {} · **Fern**",
+            SERIES, SERIES
+        );
+        let (cards, diagnostics) = parse_cards_from_qingque_atopwl(&content);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].name, "Frieren");
+        assert_eq!(cards[0].series, SERIES);
+        assert_eq!(cards[0].wishlist, Some(1234));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].field, "wishlist");
+    }
 }
@@ -1,162 +1,17 @@
 use dotenvy::dotenv;
-use serenity::all::{Embed, MessageUpdateEvent};
+use serenity::all::MessageUpdateEvent;
 use serenity::async_trait;
 use serenity::model::channel::Message;
 use serenity::prelude::*;
 use std::env;
+use swordfish_common::parsers;
 use swordfish_common::setup_logger;
-use swordfish_common::{constants, database, utils};
-use swordfish_common::{debug, tokio};
+use swordfish_common::{constants, tokio};
 use swordfish_common::{error, info, trace};
 
 const GITHUB_URL: &str = "https://github.com/teppyboy/swordfish";
 
-async fn parse_katana(ctx: &Context, msg: &Message) -> Result<(), String> {
-    if msg.embeds.len() == 0 {
-        return Ok(());
-    }
-    let embed = &msg.embeds[0];
-    parse_katana_embed(embed).await;
-    Ok(())
-}
-
-async fn parse_katana_embed(embed: &Embed) {
-    match embed.author {
-        Some(ref author) => match author.name.as_str() {
-            "Card Collection" => {
-                let cards = utils::katana::parse_cards_from_katana_kc_ow(
-                    &embed.description.as_ref().unwrap(),
-                );
-                if cards.len() == 0 {
-                    return;
-                }
-                debug!("Importing cards from Katana 'Card Collection'");
-                match database::katana::write_characters(cards).await {
-                    Ok(_) => {
-                        debug!("Imported successully");
-                    }
-                    Err(why) => {
-                        error!("Failed to import card: {:?}", why);
-                    }
-                }
-            }
-            _ => {}
-        },
-        None => {}
-    };
-    match embed.title {
-        Some(ref title) => match title.as_str() {
-            "Character Lookup" => {
-                let card = match utils::katana::parse_cards_from_katana_klu_lookup(
-                    &embed.description.as_ref().unwrap(),
-                ) {
-                    Some(card) => card,
-                    None => {
-                        return;
-                    }
-                };
-                debug!("Importing a card from Katana 'Character Lookup'");
-                match database::katana::write_character(card).await {
-                    Ok(_) => {
-                        debug!("Imported successully");
-                    }
-                    Err(why) => {
-                        error!("Failed to import card: {:?}", why);
-                    }
-                }
-            }
-            "Character Results" => {
-                let fields = match embed.fields.len() {
-                    0 => {
-                        return;
-                    }
-                    _ => &embed.fields,
-                };
-                let embed_field = fields.get(0).unwrap();
-                let cards = utils::katana::parse_cards_from_katana_klu_results(&embed_field.value);
-                if cards.len() == 0 {
-                    return;
-                }
-                debug!("Importing cards from Katana 'Character Results'");
-                match database::katana::write_characters(cards).await {
-                    Ok(_) => {
-                        debug!("Imported successully");
-                    }
-                    Err(why) => {
-                        error!("Failed to import card: {:?}", why);
-                    }
-                }
-            }
-            _ => {}
-        },
-        None => {}
-    };
-}
-
-async fn parse_calf_event(ctx: &Context, event: MessageUpdateEvent) -> Result<(), String> {
-    if event.content.is_none() {
-        return Ok(());
-    }
-    let content = event.content.unwrap();
-    if content.contains("Apricot v6 Drop Analysis Engine") {
-        let cards = utils::katana::parse_cards_from_calf_analysis(&content);
-        if cards.len() == 0 {
-            return Ok(());
-        }
-        debug!("Importing cards from Calf Analysis");
-        match database::katana::write_characters(cards).await {
-            Ok(_) => {
-                debug!("Imported successully");
-            }
-            Err(why) => {
-                error!("Failed to import card: {:?}", why);
-            }
-        }
-    }
-    Ok(())
-}
-
-async fn parse_qingque_event(ctx: &Context, event: MessageUpdateEvent) -> Result<(), String> {
-    if event.embeds.is_none() || event.embeds.clone().unwrap().len() == 0 {
-        return Ok(());
-    }
-    let embed = &event.embeds.unwrap()[0];
-    let embed_title = match embed.title {
-        Some(ref title) => title,
-        None => {
-            return Ok(());
-        }
-    };
-    match embed_title.as_str() {
-        "Top Wishlist" => {
-            let cards = utils::katana::parse_cards_from_qingque_atopwl(
-                &embed.description.as_ref().unwrap(),
-            );
-            debug!("Importing cards from Qingque 'Top Wishlist'");
-            match database::katana::write_characters(cards).await {
-                Ok(_) => {
-                    debug!("Imported successully");
-                }
-                Err(why) => {
-                    error!("Failed to import card: {:?}", why);
-                }
-            }
-        }
-        _ => {
-            return Ok(());
-        }
-    }
-    Ok(())
-}
-
-async fn parse_katana_event(ctx: &Context, event: MessageUpdateEvent) -> Result<(), String> {
-    if event.embeds.is_none() || event.embeds.clone().unwrap().len() == 0 {
-        return Ok(());
-    }
-    let embed = &event.embeds.unwrap()[0];
-    parse_katana_embed(embed).await;
-    Ok(())
-}
+swordfish_common::message_data_conversions!();
 
 struct Handler;
 
@@ -164,7 +19,7 @@ struct Handler;
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, msg: Message) {
         if msg.author.id.get() == constants::KATANA_ID {
-            parse_katana(&ctx, &msg).await.unwrap();
+            parsers::registry().dispatch(&message_data(&msg)).await;
         }
     }
     async fn message_update(
@@ -190,18 +45,9 @@ impl EventHandler for Handler {
             }
         };
         trace!("Message update: {}, sender: {}", content, author.id);
-        match author.id.get() {
-            constants::KATANA_ID => {
-                parse_katana_event(&ctx, event).await.unwrap();
-            }
-            constants::QINGQUE_ID => {
-                parse_qingque_event(&ctx, event).await.unwrap();
-            }
-            constants::CALF_ID => {
-                parse_calf_event(&ctx, event).await.unwrap();
-            }
-            _ => {}
-        }
+        parsers::registry()
+            .dispatch(&message_update_data(&event))
+            .await;
     }
 }
 
@@ -0,0 +1,224 @@
+use dotenvy::dotenv;
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use swordfish::config::Config;
+use swordfish::katana::{self, CardOutcome};
+use swordfish::{fingerprint, regression, CONFIG};
+use swordfish_common::structs::DroppedCard;
+use swordfish_common::{error, setup_logger};
+
+const USAGE: &str =
+    "Usage: swordfish-analyze [--json] [--lookup] [--bot <bot>] <image or directory>...";
+
+struct Args {
+    json: bool,
+    lookup: bool,
+    bot: String,
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct CardResult {
+    #[serde(flatten)]
+    card: Option<DroppedCard>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FileResult {
+    file: String,
+    cards: Vec<CardResult>,
+    error: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        json: false,
+        lookup: false,
+        bot: "katana".to_string(),
+        paths: Vec::new(),
+    };
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => args.json = true,
+            "--lookup" => args.lookup = true,
+            "--bot" => match iter.next() {
+                Some(bot) => args.bot = bot,
+                None => return Err("Missing value for --bot".to_string()),
+            },
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => args.paths.push(PathBuf::from(arg)),
+        }
+    }
+    if args.paths.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    Ok(args)
+}
+
+fn collect_images(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut images: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            images.extend(regression::find_images(path)?);
+        } else {
+            images.push(path.clone());
+        }
+    }
+    Ok(images)
+}
+
+async fn analyze_file(path: &Path, bot: &str, lookup: bool) -> Result<Vec<CardOutcome>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(why) => return Err(format!("Failed to read image: {:?}", why)),
+    };
+    let img = katana::decode_image(&bytes)?;
+    katana::analyze_drop_image(img, bot, &None, lookup, katana::drop_deadline()).await
+}
+
+fn print_table(results: &[FileResult]) {
+    let header = [
+        "File", "#", "Print", "Name", "Series", "Match", "Name %", "Series %",
+    ];
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for result in results {
+        if let Some(why) = &result.error {
+            rows.push(vec![
+                result.file.clone(),
+                "-".to_string(),
+                "-".to_string(),
+                format!("Error: {}", why),
+            ]);
+            continue;
+        }
+        for (i, card) in result.cards.iter().enumerate() {
+            let card = match (&card.card, &card.error) {
+                (Some(card), _) => card,
+                (None, why) => {
+                    rows.push(vec![
+                        result.file.clone(),
+                        i.to_string(),
+                        "-".to_string(),
+                        format!("Error: {}", why.clone().unwrap_or_default()),
+                    ]);
+                    continue;
+                }
+            };
+            rows.push(vec![
+                result.file.clone(),
+                i.to_string(),
+                card.print.to_string(),
+                card.character.name.clone(),
+                card.character.series.clone(),
+                format!("{:?}", card.match_kind),
+                format!("{:.1}", card.name_confidence.mean),
+                format!("{:.1}", card.series_confidence.mean),
+            ]);
+        }
+    }
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let format_row = |row: &[String]| -> String {
+        row.iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{:width$}", cell, width = widths[i]))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    println!("{}", format_row(&header));
+    for row in &rows {
+        println!("{}", format_row(row));
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(why) = dotenv() {
+        eprintln!("Failed to load .env: {:?}", why);
+    }
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(why) => {
+            eprintln!("{}", why);
+            std::process::exit(2);
+        }
+    };
+    // Same as the bot, but don't create the config file.
+    let config = match Path::new("./config.toml").exists() {
+        true => Config::load("./config.toml"),
+        false => Config::new(),
+    };
+    let log_level = env::var("LOG_LEVEL").unwrap_or(config.log.level.clone());
+    CONFIG
+        .set(config)
+        .expect("Failed to register config to static");
+    setup_logger(&log_level).expect("Failed to setup logger");
+    // Nor the correction data files
+    swordfish::init_analyzer(false).await;
+    if args.lookup {
+        swordfish_common::database::init().await;
+        if CONFIG.get().unwrap().analyzer.fingerprint.enabled {
+            fingerprint::init().await;
+        }
+    }
+    let images = match collect_images(&args.paths) {
+        Ok(images) => images,
+        Err(why) => {
+            eprintln!("{}", why);
+            std::process::exit(2);
+        }
+    };
+    let mut results: Vec<FileResult> = Vec::with_capacity(images.len());
+    for image in images {
+        let file = image.display().to_string();
+        match analyze_file(&image, &args.bot, args.lookup).await {
+            Ok(cards) => results.push(FileResult {
+                file,
+                cards: cards
+                    .into_iter()
+                    .map(|card| match card {
+                        Ok(card) => CardResult {
+                            card: Some(card),
+                            error: None,
+                        },
+                        Err(why) => CardResult {
+                            card: None,
+                            error: Some(why.to_string()),
+                        },
+                    })
+                    .collect(),
+                error: None,
+            }),
+            Err(why) => {
+                error!("Failed to analyze {}: {}", file, why);
+                results.push(FileResult {
+                    file,
+                    cards: Vec::new(),
+                    error: Some(why),
+                });
+            }
+        }
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&results).unwrap());
+    } else {
+        print_table(&results);
+    }
+    if results.iter().any(|result| {
+        result.error.is_some() || result.cards.iter().any(|card| card.error.is_some())
+    }) {
+        std::process::exit(1);
+    }
+}
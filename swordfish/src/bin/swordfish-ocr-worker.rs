@@ -0,0 +1,54 @@
+use std::io::{self, BufWriter};
+use std::path::Path;
+use swordfish::config::Config;
+use swordfish::tesseract::{libtesseract, worker};
+use swordfish::CONFIG;
+
+// Reads images from stdin with libtesseract and writes the text and TSV output to stdout,
+// see `tesseract::worker` for the protocol. Errors go to stderr since stdout is the pipe.
+fn main() {
+    let config = match Path::new("./config.toml").exists() {
+        true => Config::load("./config.toml"),
+        false => Config::new(),
+    };
+    CONFIG
+        .set(config)
+        .expect("Failed to register config to static");
+    let mut engines = match (
+        libtesseract::create_tesseract(false),
+        libtesseract::create_tesseract(true),
+    ) {
+        (Ok(text), Ok(numeric)) => [text, numeric],
+        (Err(why), _) | (_, Err(why)) => {
+            eprintln!("Failed to create Tesseract: {}", why);
+            std::process::exit(1);
+        }
+    };
+    let mut reader = io::stdin().lock();
+    let mut writer = BufWriter::new(io::stdout().lock());
+    loop {
+        let (numeric, dpi, png) = match worker::read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(why) => {
+                eprintln!("Failed to read request: {:?}", why);
+                std::process::exit(1);
+            }
+        };
+        let engine = &mut engines[numeric as usize];
+        let result = match libtesseract::set_dpi(engine, dpi) {
+            Ok(_) => match engine.set_image_from_mem(&png) {
+                Ok(_) => match engine.get_utf8_text() {
+                    Ok(text) => libtesseract::get_tsv(engine).map(|tsv| (text, tsv)),
+                    Err(why) => Err(format!("Failed to read text: {:?}", why)),
+                },
+                Err(why) => Err(format!("Failed to set image: {:?}", why)),
+            },
+            Err(why) => Err(why),
+        };
+        if let Err(why) = worker::write_response(&mut writer, &result) {
+            eprintln!("Failed to write response: {:?}", why);
+            std::process::exit(1);
+        }
+    }
+}
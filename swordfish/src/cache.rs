@@ -0,0 +1,119 @@
+use crate::katana::CardOutcome;
+use crate::CONFIG;
+use image::DynamicImage;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use swordfish_common::trace;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+///
+/// A drop image and the cards analyzed from it, if the analysis fully succeeded.
+///
+struct CachedDrop {
+    key: String,
+    image: DynamicImage,
+    cards: Option<Vec<CardOutcome>>,
+    cached_at: Instant,
+}
+
+// Most recently used drops last.
+static CACHE: Mutex<Vec<CachedDrop>> = Mutex::new(Vec::new());
+// Drops being analyzed, with the lock held during their analysis.
+static IN_FLIGHT: Mutex<Vec<(String, Arc<AsyncMutex<()>>)>> = Mutex::new(Vec::new());
+
+fn with_entry<T>(key: &str, f: impl FnOnce(&mut CachedDrop) -> T) -> Option<T> {
+    let ttl = Duration::from_secs(CONFIG.get().unwrap().analyzer.downloads.cache_ttl_secs);
+    let mut cache = CACHE.lock().unwrap();
+    cache.retain(|entry| entry.cached_at.elapsed() < ttl);
+    let index = cache.iter().position(|entry| entry.key == key)?;
+    let mut entry = cache.remove(index);
+    let result = f(&mut entry);
+    cache.push(entry);
+    Some(result)
+}
+
+///
+/// The decoded image of the drop with the attachment ID (or embed image URL).
+///
+pub fn image(key: &str) -> Option<DynamicImage> {
+    with_entry(key, |entry| entry.image.clone())
+}
+
+///
+/// The past analysis of the drop.
+///
+pub fn cards(key: &str) -> Option<Vec<CardOutcome>> {
+    with_entry(key, |entry| entry.cards.clone()).flatten()
+}
+
+pub fn insert_image(key: &str, image: &DynamicImage) {
+    let size = CONFIG.get().unwrap().analyzer.downloads.cache_size;
+    if size == 0 {
+        return;
+    }
+    if with_entry(key, |entry| entry.image = image.clone()).is_some() {
+        return;
+    }
+    let mut cache = CACHE.lock().unwrap();
+    cache.push(CachedDrop {
+        key: key.to_string(),
+        image: image.clone(),
+        cards: None,
+        cached_at: Instant::now(),
+    });
+    while cache.len() > size {
+        let evicted = cache.remove(0);
+        trace!("Evicted drop {} from the cache", evicted.key);
+    }
+}
+
+///
+/// Remember the analysis of a drop whose image is cached.
+///
+pub fn insert_cards(key: &str, cards: &[CardOutcome]) {
+    with_entry(key, |entry| {
+        entry.cards = Some(cards.to_vec());
+        entry.cached_at = Instant::now();
+    });
+}
+
+///
+/// Held while a drop is analyzed, see `lock`.
+///
+pub struct InFlight {
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        self.guard = None;
+        // Forget the lock once nobody is waiting for it
+        in_flight.retain(|(key, lock)| key != &self.key || Arc::strong_count(lock) > 1);
+    }
+}
+
+///
+/// Wait until no other analysis of the drop is in progress.
+///
+/// Duplicate events for a drop then find the analysis of the first one in the cache
+/// instead of doing it again.
+///
+pub async fn lock(key: &str) -> InFlight {
+    let lock = {
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        match in_flight.iter().find(|(other, _)| other == key) {
+            Some((_, lock)) => lock.clone(),
+            None => {
+                let lock = Arc::new(AsyncMutex::new(()));
+                in_flight.push((key.to_string(), lock.clone()));
+                lock
+            }
+        }
+    };
+    InFlight {
+        key: key.to_string(),
+        guard: Some(lock.lock_owned().await),
+    }
+}
@@ -0,0 +1,179 @@
+use crate::helper;
+use crate::CONFIG;
+use image::DynamicImage;
+use serde::Serialize;
+use serenity::model::channel::Message;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use swordfish_common::structs::{Character, MatchKind, OcrConfidence};
+use swordfish_common::{error, trace, warn};
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RegionRead {
+    pub region: String,
+    pub variant: String,
+    pub raw: String,
+    pub text: String,
+    pub confidence: f32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CardManifest {
+    pub reads: Vec<RegionRead>,
+    pub name: String,
+    pub series: String,
+    pub name_regex: String,
+    pub series_regex: String,
+    pub name_confidence: OcrConfidence,
+    pub series_confidence: OcrConfidence,
+    // Hex card art hash.
+    pub fingerprint: Option<String>,
+    pub match_kind: Option<MatchKind>,
+    pub character: Option<Character>,
+    pub error: Option<String>,
+    // Milliseconds taken by each stage.
+    pub timings: BTreeMap<String, u128>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub message_id: u64,
+    pub channel_id: u64,
+    pub guild_id: Option<u64>,
+    pub timestamp: u64,
+    pub layout: Option<String>,
+    pub cards: Vec<CardManifest>,
+    pub error: Option<String>,
+    // Milliseconds taken by each stage.
+    pub timings: BTreeMap<String, u128>,
+}
+
+///
+/// Debug artifacts of a single drop, saved into a folder named after the message ID.
+///
+pub struct DropCapture {
+    dir: PathBuf,
+    manifest: Mutex<Manifest>,
+}
+
+impl DropCapture {
+    ///
+    /// Start capturing the drop if capturing is enabled for the message's server or channel.
+    ///
+    /// Drops are always captured when the log level is "trace".
+    ///
+    pub fn new(message: &Message) -> Option<Arc<DropCapture>> {
+        let config = CONFIG.get().unwrap();
+        if !helper::message_in_list(message, &config.debug.capture.list)
+            && config.log.level != "trace"
+        {
+            return None;
+        }
+        let dir = PathBuf::from(&config.debug.capture.path).join(message.id.get().to_string());
+        if let Err(why) = fs::create_dir_all(&dir) {
+            warn!("Failed to create capture directory: {:?}", why);
+            return None;
+        }
+        trace!("Capturing drop to {}", dir.display());
+        Some(Arc::new(DropCapture {
+            dir,
+            manifest: Mutex::new(Manifest {
+                message_id: message.id.get(),
+                channel_id: message.channel_id.get(),
+                guild_id: message.guild_id.map(|id| id.get()),
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+                ..Default::default()
+            }),
+        }))
+    }
+
+    pub fn save_image(&self, img: &DynamicImage, name: &str) {
+        let path = self.dir.join(name);
+        match img.save(&path) {
+            Ok(_) => {
+                trace!("Saved image to {}", path.display());
+            }
+            Err(why) => {
+                warn!("{}", format!("Failed to save image: {:?}", why))
+            }
+        };
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut Manifest)) {
+        f(&mut self.manifest.lock().unwrap());
+    }
+
+    pub fn update_card(&self, index: u32, f: impl FnOnce(&mut CardManifest)) {
+        let mut manifest = self.manifest.lock().unwrap();
+        let index = index as usize;
+        if manifest.cards.len() <= index {
+            manifest.cards.resize(index + 1, CardManifest::default());
+        }
+        f(&mut manifest.cards[index]);
+    }
+
+    pub fn timing(&self, stage: &str, duration: Duration) {
+        self.update(|m| {
+            m.timings.insert(stage.to_string(), duration.as_millis());
+        });
+    }
+
+    pub fn card_timing(&self, index: u32, stage: &str, duration: Duration) {
+        self.update_card(index, |c| {
+            c.timings.insert(stage.to_string(), duration.as_millis());
+        });
+    }
+
+    ///
+    /// Write the manifest and remove old captures exceeding the retention limits.
+    ///
+    pub fn finish(&self) {
+        let manifest = self.manifest.lock().unwrap();
+        match serde_json::to_string_pretty(&*manifest) {
+            Ok(json) => {
+                if let Err(why) = fs::write(self.dir.join("manifest.json"), json) {
+                    error!("Failed to write capture manifest: {:?}", why);
+                }
+            }
+            Err(why) => {
+                error!("Failed to serialize capture manifest: {:?}", why);
+            }
+        }
+        apply_retention();
+    }
+}
+
+fn apply_retention() {
+    let capture_config = &CONFIG.get().unwrap().debug.capture;
+    let entries = match fs::read_dir(&capture_config.path) {
+        Ok(entries) => entries,
+        Err(why) => {
+            warn!("Failed to read capture directory: {:?}", why);
+            return;
+        }
+    };
+    let mut captures: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("manifest.json").exists())
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    // Newest first
+    captures.sort_by(|a, b| b.0.cmp(&a.0));
+    let max_age = Duration::from_secs(capture_config.max_age_hours * 60 * 60);
+    for (i, (modified, path)) in captures.into_iter().enumerate() {
+        let expired = modified.elapsed().map(|age| age > max_age).unwrap_or(false);
+        if i < capture_config.max_drops && !expired {
+            continue;
+        }
+        trace!("Removing old capture {}", path.display());
+        if let Err(why) = fs::remove_dir_all(&path) {
+            warn!("Failed to remove old capture: {:?}", why);
+        }
+    }
+}
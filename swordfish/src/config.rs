@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -12,14 +13,107 @@ pub struct Log {
     pub file: FileLog,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TesseractProfile {
+    // Language packs joined with "+", e.g. "eng+jpn".
+    pub lang: String,
+    pub psm: i32,
+    pub oem: i32,
+    pub dpi: i32,
+    // Extra Tesseract variables, e.g. tessedit_char_whitelist.
+    pub variables: BTreeMap<String, String>,
+}
+
+impl TesseractProfile {
+    pub fn text() -> TesseractProfile {
+        TesseractProfile {
+            lang: "eng".to_string(),
+            psm: 6,
+            // LSTM only.
+            oem: 1,
+            dpi: 70,
+            variables: BTreeMap::new(),
+        }
+    }
+    pub fn numeric() -> TesseractProfile {
+        TesseractProfile {
+            variables: BTreeMap::from([(
+                "tessedit_char_whitelist".to_string(),
+                "0123456789".to_string(),
+            )]),
+            ..TesseractProfile::text()
+        }
+    }
+}
+
+///
+/// Persistent `swordfish-ocr-worker` processes used by the subprocess backend
+/// instead of starting Tesseract for every read.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Workers {
+    pub enabled: bool,
+    pub size: usize,
+    // Path to swordfish-ocr-worker, empty for the one next to the current executable.
+    pub path: String,
+}
+
+impl Workers {
+    pub fn new() -> Workers {
+        Workers {
+            enabled: false,
+            size: 4,
+            path: "".to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Tesseract {
     pub backend: String,
+    // Directory containing the language packs, empty for Tesseract's default.
+    #[serde(default)]
+    pub tessdata: String,
+    // Used for names and series.
+    #[serde(default = "TesseractProfile::text")]
+    pub text: TesseractProfile,
+    // Used for prints.
+    #[serde(default = "TesseractProfile::numeric")]
+    pub numeric: TesseractProfile,
+    #[serde(default = "Workers::new")]
+    pub workers: Workers,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Capture {
+    // Servers and channels to capture drops in.
+    pub list: List,
+    pub path: String,
+    // Maximum number of captured drops to keep.
+    pub max_drops: usize,
+    pub max_age_hours: u64,
+}
+
+impl Capture {
+    pub fn new() -> Capture {
+        Capture {
+            list: List {
+                enabled: false,
+                servers: vec![],
+                channels: vec![],
+            },
+            path: "debug".to_string(),
+            max_drops: 100,
+            max_age_hours: 24 * 7,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Debug {
     pub allowed_users: Vec<u64>,
+    #[serde(default = "Capture::new")]
+    pub capture: Capture,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,6 +136,464 @@ pub struct Features {
     pub sofa_drop_analysis: DropAnalyzer,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+///
+/// Describes where the cards and their text are located in a drop image.
+///
+/// Cards are laid out horizontally, the n-th card starts at
+/// `margin_left + stride * n` and text regions are relative to the card.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CardLayout {
+    pub name: String,
+    pub bot: String,
+    // Only use this layout for images with this size, if set.
+    pub image_width: Option<u32>,
+    pub image_height: Option<u32>,
+    pub card_width: u32,
+    pub card_height: u32,
+    pub stride: u32,
+    pub margin_left: u32,
+    pub margin_top: u32,
+    pub regions: BTreeMap<String, Region>,
+}
+
+impl CardLayout {
+    pub fn katana() -> CardLayout {
+        CardLayout {
+            name: "katana".to_string(),
+            bot: "katana".to_string(),
+            image_width: None,
+            image_height: None,
+            card_width: 228,
+            card_height: 353,
+            stride: 274,
+            margin_left: 29,
+            margin_top: 34,
+            regions: BTreeMap::from([
+                (
+                    "art".to_string(),
+                    Region {
+                        x: 22,
+                        y: 76,
+                        width: 184,
+                        height: 196,
+                    },
+                ),
+                (
+                    "name".to_string(),
+                    Region {
+                        x: 22,
+                        y: 28,
+                        width: 180,
+                        height: 42,
+                    },
+                ),
+                (
+                    "series".to_string(),
+                    Region {
+                        x: 22,
+                        y: 278,
+                        width: 184,
+                        height: 50,
+                    },
+                ),
+            ]),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PreprocessStep {
+    Grayscale,
+    Contrast { value: f32 },
+    OtsuThreshold,
+    AdaptiveThreshold { block_radius: u32 },
+    Invert,
+    Upscale { factor: f32, filter: ResizeFilter },
+    Sharpen { sigma: f32, threshold: i32 },
+    MedianDenoise { radius: u32 },
+    Padding { size: u32 },
+}
+
+///
+/// Preprocessing steps applied in order before OCR.
+///
+/// `image` is applied to the whole drop before cropping, so it must not
+/// change the image size. The others are applied to each cropped text region.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Preprocess {
+    pub image: Vec<PreprocessStep>,
+    pub name: Vec<PreprocessStep>,
+    pub series: Vec<PreprocessStep>,
+    pub numeric: Vec<PreprocessStep>,
+}
+
+impl Preprocess {
+    pub fn new() -> Preprocess {
+        Preprocess {
+            image: vec![
+                PreprocessStep::Grayscale,
+                PreprocessStep::Contrast {
+                    value: 127.0 / 4.0,
+                },
+            ],
+            name: vec![PreprocessStep::Padding { size: 7 }],
+            series: vec![PreprocessStep::Padding { size: 7 }],
+            numeric: vec![PreprocessStep::Padding { size: 7 }],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub steps: Vec<PreprocessStep>,
+}
+
+///
+/// Extra preprocessing variants to read the text regions with when the first
+/// read doesn't match a character exactly, the best read is then chosen by voting.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Variants {
+    pub enabled: bool,
+    // Time budget for the whole drop, no new variant is started after it's exceeded.
+    pub time_budget_ms: u64,
+    pub list: Vec<Variant>,
+}
+
+impl Variants {
+    pub fn new() -> Variants {
+        Variants {
+            enabled: false,
+            time_budget_ms: 2000,
+            list: vec![
+                Variant {
+                    name: "otsu".to_string(),
+                    steps: vec![
+                        PreprocessStep::OtsuThreshold,
+                        PreprocessStep::Padding { size: 7 },
+                    ],
+                },
+                Variant {
+                    name: "adaptive".to_string(),
+                    steps: vec![
+                        PreprocessStep::AdaptiveThreshold { block_radius: 15 },
+                        PreprocessStep::Padding { size: 7 },
+                    ],
+                },
+                Variant {
+                    name: "upscale".to_string(),
+                    steps: vec![
+                        PreprocessStep::Upscale {
+                            factor: 2.0,
+                            filter: ResizeFilter::CatmullRom,
+                        },
+                        PreprocessStep::Padding { size: 14 },
+                    ],
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Confidence {
+    // Tesseract confidence (0 - 100) below which a read is doubtful.
+    pub min_ocr_confidence: f32,
+    // Similarity (0 - 1) between the read and the matched character below which a match is doubtful.
+    pub min_match_similarity: f32,
+}
+
+impl Confidence {
+    pub fn new() -> Confidence {
+        Confidence {
+            min_ocr_confidence: 70.0,
+            min_match_similarity: 0.8,
+        }
+    }
+}
+
+// Constrained modes are only tried when matching the name and series together finds nothing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchingMode {
+    // Match the name and the series together only.
+    Combined,
+    SeriesFirst,
+    NameFirst,
+    // Series first, unless the series read is poor.
+    Auto,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Matching {
+    pub mode: MatchingMode,
+    // Minimum similarity (0 - 1) of the constrained field to accept a character.
+    pub min_similarity: f32,
+    // Maximum characters fetched when matching the name first.
+    pub max_candidates: i64,
+}
+
+impl Matching {
+    pub fn new() -> Matching {
+        Matching {
+            mode: MatchingMode::Auto,
+            min_similarity: 0.5,
+            max_candidates: 100,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Corrections {
+    // Ordered OCR correction rules, created with the default rules if missing.
+    pub path: String,
+}
+
+impl Corrections {
+    pub fn new() -> Corrections {
+        Corrections {
+            path: "corrections.toml".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Confusions {
+    // Characters Tesseract confuses, created with the default confusions if missing.
+    pub path: String,
+    // Record the confusions of OCR texts resolved to a character.
+    pub learn: bool,
+    // Times a confusion must be seen before it's used for matching, the default confusions
+    // are counted as seen this many times.
+    pub min_count: u64,
+    // Most frequent confusions used per character.
+    pub max_alternatives: usize,
+    // Seconds between writes of the recorded confusions to the file.
+    pub save_interval_secs: u64,
+}
+
+impl Confusions {
+    pub fn new() -> Confusions {
+        Confusions {
+            path: "confusions.json".to_string(),
+            learn: true,
+            min_count: 5,
+            max_alternatives: 4,
+            save_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fingerprint {
+    // Identify cards by their art (the "art" layout region) before reading them, checked
+    // against the name read.
+    pub enabled: bool,
+    // Maximum number of differing bits between two hashes of the same art.
+    pub max_distance: u32,
+}
+
+impl Fingerprint {
+    pub fn new() -> Fingerprint {
+        Fingerprint {
+            enabled: true,
+            max_distance: 6,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dictionary {
+    // Bias Tesseract towards the names and series in the database.
+    pub enabled: bool,
+    pub words_path: String,
+    pub patterns_path: String,
+    // How often the files are regenerated from the database.
+    pub refresh_hours: u64,
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary {
+        Dictionary {
+            enabled: true,
+            words_path: "user-words.txt".to_string(),
+            patterns_path: "user-patterns.txt".to_string(),
+            refresh_hours: 6,
+        }
+    }
+}
+
+///
+/// Read the name and series of all cards in a drop with a single Tesseract call,
+/// by stacking the regions into one image.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Batch {
+    pub enabled: bool,
+    // White space between the stacked regions, in pixels.
+    pub gap: u32,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch {
+            enabled: false,
+            gap: 20,
+        }
+    }
+}
+
+///
+/// Limits on downloaded drop images, and how many recent drops to keep decoded.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Downloads {
+    pub max_bytes: u64,
+    // Checked before decoding, so huge images are never allocated.
+    pub max_width: u32,
+    pub max_height: u32,
+    pub cache_size: usize,
+    // Cached drops and analyses older than this are analyzed again.
+    pub cache_ttl_secs: u64,
+}
+
+impl Downloads {
+    pub fn new() -> Downloads {
+        Downloads {
+            max_bytes: 10 * 1024 * 1024,
+            max_width: 4096,
+            max_height: 4096,
+            cache_size: 32,
+            cache_ttl_secs: 600,
+        }
+    }
+}
+
+///
+/// Rescale drop images whose cards aren't the size of the layout (e.g. screenshots)
+/// before cropping the cards.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Normalize {
+    pub enabled: bool,
+    // Relative difference from the layout card size which is left as is.
+    pub tolerance: f32,
+    // How much a pixel must differ from the background to be part of a card.
+    pub background_threshold: u8,
+}
+
+impl Normalize {
+    pub fn new() -> Normalize {
+        Normalize {
+            enabled: true,
+            tolerance: 0.05,
+            background_threshold: 24,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Timeouts {
+    // Time limits in milliseconds, work exceeding them is abandoned.
+    pub download_ms: u64,
+    // Per read of a text region.
+    pub ocr_ms: u64,
+    // Per database lookup of a card.
+    pub lookup_ms: u64,
+    // The whole drop, from downloading the image to the last card.
+    pub drop_ms: u64,
+}
+
+impl Timeouts {
+    pub fn new() -> Timeouts {
+        Timeouts {
+            download_ms: 10000,
+            ocr_ms: 5000,
+            lookup_ms: 3000,
+            drop_ms: 20000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Analyzer {
+    pub layouts: Vec<CardLayout>,
+    // Bot whose layouts are used for drops posted by anyone else, e.g. screenshots.
+    #[serde(default = "Analyzer::default_bot")]
+    pub default_bot: String,
+    #[serde(default = "Preprocess::new")]
+    pub preprocess: Preprocess,
+    #[serde(default = "Confidence::new")]
+    pub confidence: Confidence,
+    #[serde(default = "Variants::new")]
+    pub variants: Variants,
+    #[serde(default = "Matching::new")]
+    pub matching: Matching,
+    #[serde(default = "Corrections::new")]
+    pub corrections: Corrections,
+    #[serde(default = "Confusions::new")]
+    pub confusions: Confusions,
+    #[serde(default = "Fingerprint::new")]
+    pub fingerprint: Fingerprint,
+    #[serde(default = "Dictionary::new")]
+    pub dictionary: Dictionary,
+    #[serde(default = "Timeouts::new")]
+    pub timeouts: Timeouts,
+    #[serde(default = "Batch::new")]
+    pub batch: Batch,
+    #[serde(default = "Downloads::new")]
+    pub downloads: Downloads,
+    #[serde(default = "Normalize::new")]
+    pub normalize: Normalize,
+}
+
+impl Analyzer {
+    fn default_bot() -> String {
+        "katana".to_string()
+    }
+
+    pub fn new() -> Analyzer {
+        Analyzer {
+            layouts: vec![CardLayout::katana()],
+            default_bot: Analyzer::default_bot(),
+            preprocess: Preprocess::new(),
+            confidence: Confidence::new(),
+            variants: Variants::new(),
+            matching: Matching::new(),
+            corrections: Corrections::new(),
+            confusions: Confusions::new(),
+            fingerprint: Fingerprint::new(),
+            dictionary: Dictionary::new(),
+            timeouts: Timeouts::new(),
+            batch: Batch::new(),
+            downloads: Downloads::new(),
+            normalize: Normalize::new(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct General {
     pub prefix: String,
@@ -54,6 +606,8 @@ pub struct Config {
     pub debug: Debug,
     pub features: Features,
     pub general: General,
+    #[serde(default = "Analyzer::new")]
+    pub analyzer: Analyzer,
 }
 
 impl Config {
@@ -68,9 +622,14 @@ impl Config {
             },
             tesseract: Tesseract {
                 backend: "libtesseract".to_string(),
+                tessdata: "".to_string(),
+                text: TesseractProfile::text(),
+                numeric: TesseractProfile::numeric(),
+                workers: Workers::new(),
             },
             debug: Debug {
                 allowed_users: vec![],
+                capture: Capture::new(),
             },
             features: Features {
                 katana_drop_analysis: DropAnalyzer {
@@ -103,6 +662,7 @@ impl Config {
             general: General {
                 prefix: "~".to_string(),
             },
+            analyzer: Analyzer::new(),
         }
     }
     pub fn save(&self, path: &str) {
@@ -9,8 +9,28 @@ use serenity::model::{
     id::{ChannelId, MessageId},
 };
 use serenity::prelude::*;
+use std::fmt::Debug;
+use swordfish_common::parsers::{metrics, Diagnostic};
 use tokio::time::Instant;
 
+///
+/// The parsed cards, followed by the lines which couldn't be parsed.
+///
+fn format_parsed(cards: &impl Debug, diagnostics: &[Diagnostic]) -> String {
+    let mut reply_str = format!("Parsed cards: ```\n{:?}\n```", cards);
+    if !diagnostics.is_empty() {
+        reply_str.push_str(&format!(
+            "\n{} lines failed to parse: ```\n",
+            diagnostics.len()
+        ));
+        for diagnostic in diagnostics {
+            reply_str.push_str(&format!("{}\n", diagnostic));
+        }
+        reply_str.push_str("```");
+    }
+    reply_str
+}
+
 pub async fn dbg_get_message(command: &str, ctx: &Context, msg: &Message) -> Result<Message, ()> {
     let mut args = msg.content.split(" ");
     let target_channel_id = match args.nth(2) {
@@ -117,18 +137,12 @@ pub async fn dbg_parse_qingque_atopwl(ctx: &Context, msg: &Message) -> CommandRe
             return Ok(());
         }
     };
-    let cards = utils::katana::parse_cards_from_qingque_atopwl(embed_description);
-    helper::info_message(
-        ctx,
-        msg,
-        format!("Parsed cards: ```\n{:?}\n```", cards),
-        None,
-    )
-    .await;
+    let (cards, diagnostics) = utils::katana::parse_cards_from_qingque_atopwl(embed_description);
+    helper::info_message(ctx, msg, format_parsed(&cards, &diagnostics), None).await;
     Ok(())
 }
 
-pub async fn dbg_parse_katana_kc_ow(ctx: &Context, msg: &Message) -> CommandResult {
+pub async fn dbg_parse_katana_kc(ctx: &Context, msg: &Message) -> CommandResult {
     let target_msg = match dbg_get_message("embed", ctx, msg).await {
         Ok(msg) => msg,
         Err(_) => {
@@ -159,14 +173,8 @@ pub async fn dbg_parse_katana_kc_ow(ctx: &Context, msg: &Message) -> CommandResu
             return Ok(());
         }
     };
-    let cards = utils::katana::parse_cards_from_katana_kc_ow(embed_description);
-    helper::info_message(
-        ctx,
-        msg,
-        format!("Parsed cards: ```\n{:?}\n```", cards),
-        None,
-    )
-    .await;
+    let (cards, diagnostics) = utils::katana::parse_cards_from_katana_kc(embed_description);
+    helper::info_message(ctx, msg, format_parsed(&cards, &diagnostics), None).await;
     Ok(())
 }
 
@@ -202,14 +210,9 @@ pub async fn dbg_parse_katana_klu_results(ctx: &Context, msg: &Message) -> Comma
         _ => &embed.fields,
     };
     let embed_field = fields.get(0).unwrap();
-    let cards = utils::katana::parse_cards_from_katana_klu_results(&embed_field.value);
-    helper::info_message(
-        ctx,
-        msg,
-        format!("Parsed cards: ```\n{:?}\n```", cards),
-        None,
-    )
-    .await;
+    let (cards, diagnostics) =
+        utils::katana::parse_cards_from_katana_klu_results(&embed_field.value);
+    helper::info_message(ctx, msg, format_parsed(&cards, &diagnostics), None).await;
     Ok(())
 }
 
@@ -244,14 +247,50 @@ pub async fn dbg_parse_katana_klu_lookup(ctx: &Context, msg: &Message) -> Comman
             return Ok(());
         }
     };
-    let card = match utils::katana::parse_cards_from_katana_klu_lookup(embed_description) {
-        Some(card) => card,
+    let (card, diagnostics) = utils::katana::parse_cards_from_katana_klu_lookup(embed_description);
+    if card.is_none() && diagnostics.is_empty() {
+        helper::error_message(ctx, msg, "Failed to parse card".to_string(), None).await;
+        return Ok(());
+    }
+    helper::info_message(ctx, msg, format_parsed(&card, &diagnostics), None).await;
+    Ok(())
+}
+
+pub async fn dbg_parse_katana_card_view(ctx: &Context, msg: &Message) -> CommandResult {
+    let target_msg = match dbg_get_message("embed", ctx, msg).await {
+        Ok(msg) => msg,
+        Err(_) => {
+            return Ok(());
+        }
+    };
+    if target_msg.embeds.len() == 0 {
+        helper::error_message(
+            ctx,
+            msg,
+            "Message does not contain any embeds".to_string(),
+            None,
+        )
+        .await;
+        return Ok(());
+    }
+    let embed = &target_msg.embeds[0];
+    let embed_description = match embed.description {
+        Some(ref description) => description,
         None => {
-            helper::error_message(ctx, msg, "Failed to parse card".to_string(), None).await;
+            helper::error_message(
+                ctx,
+                msg,
+                "Embed does not contain a description".to_string(),
+                None,
+            )
+            .await;
             return Ok(());
         }
     };
-    helper::info_message(ctx, msg, format!("Parsed card: ```\n{:?}\n```", card), None).await;
+    let image = embed.image.as_ref().map(|image| image.url.clone());
+    let (card, diagnostics) =
+        utils::katana::parse_card_from_katana_card_view(embed_description, image.as_ref());
+    helper::info_message(ctx, msg, format_parsed(&card, &diagnostics), None).await;
     Ok(())
 }
 
@@ -262,14 +301,8 @@ pub async fn dbg_parse_calf_analysis(ctx: &Context, msg: &Message) -> CommandRes
             return Ok(());
         }
     };
-    let cards = utils::katana::parse_cards_from_calf_analysis(&target_msg.content);
-    helper::info_message(
-        ctx,
-        msg,
-        format!("Parsed cards: ```\n{:?}\n```", cards),
-        None,
-    )
-    .await;
+    let (cards, diagnostics) = utils::katana::parse_cards_from_calf_analysis(&target_msg.content);
+    helper::info_message(ctx, msg, format_parsed(&cards, &diagnostics), None).await;
     Ok(())
 }
 
@@ -397,37 +430,7 @@ pub async fn dbg_kdropanalyze(ctx: &Context, msg: &Message) -> CommandResult {
     match katana::analyze_drop_message(&target_msg).await {
         Ok(cards) => {
             let duration = start.elapsed();
-            let mut reply_str = String::new();
-            for card in cards {
-                // reply_str.push_str(&format!("{:?}\n", card));
-                let wishlist_str: String = match card.character.wishlist {
-                    Some(wishlist) => {
-                        let mut out_str = wishlist.to_string();
-                        while out_str.len() < 5 {
-                            out_str.push(' ');
-                        }
-                        out_str
-                    }
-                    None => "None ".to_string(),
-                };
-                let last_update_ts_str = match card.character.last_update_ts {
-                    0 => "`Never`".to_string(),
-                    ts => {
-                        format!("<t:{}:R>", ts.to_string())
-                    }
-                };
-                reply_str.push_str(
-                    format!(
-                        ":heart: `{}` • `{}` • **{}** • {} • {}\n",
-                        wishlist_str,
-                        card.print,
-                        card.character.name,
-                        card.character.series,
-                        last_update_ts_str
-                    )
-                    .as_str(),
-                )
-            }
+            let mut reply_str = katana::format_cards(&cards, true);
             reply_str.push_str(&format!("Time taken (to analyze): `{:?}`", duration));
             msg.reply(ctx, reply_str).await?;
         }
@@ -445,10 +448,17 @@ pub async fn dbg_kdropanalyze(ctx: &Context, msg: &Message) -> CommandResult {
 }
 
 pub async fn dbg_info(ctx: &Context, msg: &Message) -> CommandResult {
-    let reply_str = format!(
+    let mut reply_str = format!(
         "Tesseract backend: {}",
         CONFIG.get().unwrap().tesseract.backend,
     );
+    reply_str.push_str("\n\nParsers (messages / records / failed lines / failed messages):");
+    for (name, parser) in metrics::snapshot() {
+        reply_str.push_str(&format!(
+            "\n{}: `{}` / `{}` / `{}` / `{}`",
+            name, parser.messages, parser.records, parser.diagnostics, parser.failed_messages
+        ));
+    }
     helper::info_message(ctx, msg, reply_str, Some("Debug Information".to_string())).await;
     Ok(())
 }
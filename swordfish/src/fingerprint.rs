@@ -0,0 +1,127 @@
+use crate::CONFIG;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use std::sync::{OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use swordfish_common::database::katana as db;
+use swordfish_common::structs::{Character, Fingerprint};
+use swordfish_common::{error, info, trace};
+use tokio::task;
+
+static INDEX: OnceLock<RwLock<Vec<Fingerprint>>> = OnceLock::new();
+
+///
+/// Load the fingerprint index from the database.
+///
+pub async fn init() {
+    let fingerprints = match db::query_fingerprints().await {
+        Ok(fingerprints) => fingerprints,
+        Err(why) => {
+            error!("Failed to load fingerprints: {}", why);
+            Vec::new()
+        }
+    };
+    info!("Loaded {} card art fingerprints", fingerprints.len());
+    INDEX.set(RwLock::new(fingerprints)).unwrap();
+}
+
+///
+/// Difference hash of the image, robust to small changes in scale, brightness and compression.
+///
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash: u64 = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | (left > right) as u64;
+        }
+    }
+    hash
+}
+
+fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+///
+/// Find the character whose card art is closest to the hash, with the distance.
+///
+/// Returns `None` if nothing is close enough, or if another character is as close,
+/// since the art can't tell them apart.
+///
+pub fn find(hash: u64) -> Option<(Fingerprint, u32)> {
+    let index = INDEX.get()?.read().unwrap();
+    let max_distance = CONFIG.get().unwrap().analyzer.fingerprint.max_distance;
+    let mut candidates: Vec<(&Fingerprint, u32)> = index
+        .iter()
+        .map(|fp| (fp, distance(fp.hash as u64, hash)))
+        .filter(|(_, d)| *d <= max_distance)
+        .collect();
+    candidates.sort_by_key(|(fp, d)| (*d, u32::MAX - fp.count));
+    let (best, best_distance) = candidates.first()?;
+    let ambiguous = candidates
+        .iter()
+        .any(|(fp, d)| *d == *best_distance && (fp.name != best.name || fp.series != best.series));
+    if ambiguous {
+        trace!("Fingerprint {:016x} matches multiple characters", hash);
+        return None;
+    }
+    Some(((*best).clone(), *best_distance))
+}
+
+///
+/// Remember that the card art with the hash is the character.
+///
+pub fn remember(hash: u64, character: &Character) {
+    let index = match INDEX.get() {
+        Some(index) => index,
+        None => return,
+    };
+    {
+        let mut index = index.write().unwrap();
+        match index.iter_mut().find(|fp| {
+            fp.hash as u64 == hash && fp.name == character.name && fp.series == character.series
+        }) {
+            Some(fp) => fp.count += 1,
+            None => index.push(Fingerprint {
+                hash: hash as i64,
+                name: character.name.clone(),
+                series: character.series.clone(),
+                count: 1,
+                last_update_ts: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+            }),
+        }
+    }
+    let name = character.name.clone();
+    let series = character.series.clone();
+    task::spawn(async move {
+        if let Err(why) = db::write_fingerprint(hash as i64, &name, &series).await {
+            error!("{}", why);
+        }
+    });
+}
+
+///
+/// Forget that the card art with the hash is the character, e.g. after the name read disagreed.
+///
+pub fn forget(hash: u64, character: &Character) {
+    let index = match INDEX.get() {
+        Some(index) => index,
+        None => return,
+    };
+    index.write().unwrap().retain(|fp| {
+        fp.hash as u64 != hash || fp.name != character.name || fp.series != character.series
+    });
+    let name = character.name.clone();
+    let series = character.series.clone();
+    task::spawn(async move {
+        if let Err(why) = db::delete_fingerprint(hash as i64, &name, &series).await {
+            error!("{}", why);
+        }
+    });
+}
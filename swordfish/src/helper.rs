@@ -5,23 +5,32 @@ use serenity::client::Context;
 use serenity::model::channel::Message;
 use swordfish_common::error;
 
-pub fn message_in_blacklist(msg: &Message, blacklist: &List) -> bool {
-    if !blacklist.enabled {
+///
+/// Whether the list is enabled and contains the message's server or channel.
+///
+pub fn message_in_list(msg: &Message, list: &List) -> bool {
+    if !list.enabled {
         return false;
     }
     let guild_id = match msg.guild_id {
         Some(id) => id,
         None => return false,
     };
-    if blacklist.servers.contains(&guild_id.get()) {
+    if list.servers.contains(&guild_id.get()) {
         return true;
     }
-    if blacklist.channels.contains(&msg.channel_id.get()) {
+    if list.channels.contains(&msg.channel_id.get()) {
         return true;
     }
     return false;
 }
 
+swordfish_common::message_data_conversions!(pub);
+
+pub fn message_in_blacklist(msg: &Message, blacklist: &List) -> bool {
+    message_in_list(msg, blacklist)
+}
+
 pub fn message_in_whitelist(msg: &Message, whitelist: &List) -> bool {
     if !whitelist.enabled {
         return true;
@@ -1,384 +1,1251 @@
+use crate::cache;
+use crate::capture::{DropCapture, RegionRead};
+use crate::config::{CardLayout, MatchingMode, PreprocessStep, Region};
+use crate::fingerprint;
 use crate::helper;
-use crate::tesseract::utils::{fix_tesseract_string, regexify_text};
-use crate::tesseract::{libtesseract, subprocess};
+use crate::normalize;
+use crate::preprocess;
+use crate::tesseract::batch::{self, OcrWord};
+use crate::tesseract::libtesseract::Engine;
+use crate::tesseract::utils::{fix_tesseract_string, regexify_text, similarity};
+use crate::tesseract::{confusions, libtesseract, subprocess};
 use crate::CONFIG;
-use image::imageops::colorops::contrast_in_place;
 use image::io::Reader as ImageReader;
-use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageFormat, Rgba};
+use image::{DynamicImage, ImageFormat, Limits};
 use serenity::all::Context;
-use serenity::model::channel::Message;
+use serenity::model::channel::{Attachment, Message};
+use std::cmp::Ordering;
+use std::fmt;
+use std::future::Future;
 use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use swordfish_common::constants;
 use swordfish_common::database::katana as db;
-use swordfish_common::structs::{Character, DroppedCard};
+use swordfish_common::structs::{Character, DroppedCard, MatchKind, OcrConfidence};
 use swordfish_common::{error, trace, warn};
 use tokio::task;
-use tokio::time::Instant;
-
-const CARD_NAME_X_OFFSET: u32 = 22;
-const CARD_NAME_Y_OFFSET: u32 = 28;
-const CARD_NAME_WIDTH: u32 = 202 - CARD_NAME_X_OFFSET;
-const CARD_NAME_HEIGHT: u32 = 70 - CARD_NAME_Y_OFFSET;
-const CARD_SERIES_X_OFFSET: u32 = 22;
-const CARD_SERIES_Y_OFFSET: u32 = 278;
-const CARD_SERIES_WIDTH: u32 = 206 - CARD_SERIES_X_OFFSET;
-const CARD_SERIES_HEIGHT: u32 = 328 - CARD_SERIES_Y_OFFSET;
-
-fn save_image_if_trace(img: &DynamicImage, path: &str) {
-    let log_lvl = CONFIG.get().unwrap().log.level.as_str();
-    if log_lvl == "trace" {
-        match img.save(path) {
-            Ok(_) => {
-                trace!("Saved image to {}", path);
-            }
-            Err(why) => {
-                warn!("{}", format!("Failed to save image: {:?}", why))
-            }
-        };
-    }
+use tokio::time::{self, Duration, Instant};
+
+// How many of the best reads of each region are tried against the database when voting.
+const VOTE_CANDIDATES: usize = 3;
+// Alphanumeric characters a read needs to be used for constrained matching.
+const MIN_CONSTRAINED_READ: usize = 3;
+
+pub type Capture = Option<Arc<DropCapture>>;
+// The (name, series) reads of a card from a batch read of the drop.
+type BatchRead = ((String, OcrConfidence), (String, OcrConfidence));
+// The outcome of a single card, so one failing card doesn't fail the whole drop.
+pub type CardOutcome = Result<DroppedCard, CardError>;
+
+#[derive(Debug, Clone)]
+pub enum CardError {
+    Failed(String),
+    // The stage ("ocr", "lookup" or "drop") which ran out of time.
+    TimedOut(&'static str),
 }
 
-fn image_with_white_padding(im: DynamicImage) -> DynamicImage {
-    // Partially copied from https://github.com/PureSci/nori/blob/main/rust-workers/src/drop.rs#L102C1-L121C6
-    let mut new_im: DynamicImage =
-        ImageBuffer::<Rgba<u8>, Vec<u8>>::new(im.width() + 14, im.height() + 14).into();
-    let white = Rgba([255, 255, 255, 255]);
-    for y in 0..im.height() {
-        for x in 0..im.width() {
-            let p = im.get_pixel(x, y);
-            new_im.put_pixel(x + 7, y + 7, p.to_owned());
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardError::Failed(why) => write!(f, "{}", why),
+            CardError::TimedOut(stage) => write!(f, "Timed out ({})", stage),
         }
     }
-    for y in 0..7 {
-        for x in 0..im.width() + 14 {
-            new_im.put_pixel(x, y, white);
-            new_im.put_pixel(x, y + im.height() + 7, white);
-        }
+}
+
+impl From<String> for CardError {
+    fn from(why: String) -> CardError {
+        CardError::Failed(why)
     }
-    for x in 0..7 {
-        for y in 7..im.height() + 7 {
-            new_im.put_pixel(x, y, white);
-            new_im.put_pixel(x + im.width() + 7, y, white);
+}
+
+///
+/// Run the future with a time limit in milliseconds.
+///
+/// Timed out blocking work (e.g. a Tesseract subprocess) can't be stopped,
+/// so it's abandoned and its result is dropped once it finishes.
+///
+async fn with_timeout<T>(
+    stage: &'static str,
+    ms: u64,
+    future: impl Future<Output = T>,
+) -> Result<T, CardError> {
+    match time::timeout(Duration::from_millis(ms), future).await {
+        Ok(result) => Ok(result),
+        Err(_) => {
+            warn!("Card analysis timed out ({})", stage);
+            Err(CardError::TimedOut(stage))
         }
     }
-    new_im
 }
 
-pub async fn analyze_card_libtesseract(
-    card: image::DynamicImage,
-    count: u32,
-) -> Result<DroppedCard, String> {
-    trace!("Spawning threads for analyzing card...");
-    // Read the name and the series
-    let card_clone = card.clone();
-    let name_thread = task::spawn_blocking(move || {
-        // let mut leptess =
-        //     libtesseract::init_tesseract(false).expect("Failed to initialize Tesseract");
-        let binding = unsafe {
-            match libtesseract::get_tesseract() {
-                Ok(b) => b,
-                Err(why) => {
-                    panic!("{}", format!("Failed to get Tesseract: {:?}", why));
-                }
+///
+/// The deadline of a drop analysis starting now.
+///
+pub fn drop_deadline() -> Instant {
+    Instant::now() + Duration::from_millis(CONFIG.get().unwrap().analyzer.timeouts.drop_ms)
+}
+
+///
+/// The bot whose layouts apply to the drop, the configured default bot for drops
+/// posted by anyone else (e.g. a screenshot sent to `debug kda`).
+///
+fn bot_name(message: &Message) -> &'static str {
+    match message.author.id.get() {
+        constants::KATANA_ID => "katana",
+        constants::SOFA_ID => "sofa",
+        _ => &CONFIG.get().unwrap().analyzer.default_bot,
+    }
+}
+
+///
+/// Select the card layout for the bot, preferring layouts made for the image size.
+///
+/// `size` gives the size of the image once normalized to a layout, so scaled images
+/// match the layouts made for the size they're normalized to.
+///
+pub fn select_layout<'a>(
+    layouts: &'a [CardLayout],
+    bot: &str,
+    size: impl Fn(&CardLayout) -> (u32, u32),
+) -> Option<&'a CardLayout> {
+    // Reversed so the first matching layout in the config wins ties.
+    layouts
+        .iter()
+        .rev()
+        .filter(|layout| layout.bot == bot)
+        .filter(|layout| {
+            let (width, height) = size(layout);
+            layout.image_width.unwrap_or(width) == width
+                && layout.image_height.unwrap_or(height) == height
+        })
+        .max_by_key(|layout| {
+            layout.image_width.is_some() as u8 + layout.image_height.is_some() as u8
+        })
+}
+
+fn crop_region(card: &DynamicImage, region: &Region) -> DynamicImage {
+    card.crop_imm(region.x, region.y, region.width, region.height)
+}
+
+fn parse_print(text: &str) -> i32 {
+    let digits: String = text.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.parse::<i32>().unwrap_or(0)
+}
+
+fn get_region(layout: &CardLayout, name: &str) -> Result<Region, String> {
+    match layout.regions.get(name) {
+        Some(region) => Ok(*region),
+        None => Err(format!(
+            "Layout '{}' does not have a '{}' region",
+            layout.name, name
+        )),
+    }
+}
+
+///
+/// Whether the OCR read is too short to constrain a lookup.
+///
+fn is_short_read(text: &str) -> bool {
+    text.chars().filter(|c| c.is_alphanumeric()).count() < MIN_CONSTRAINED_READ
+}
+
+///
+/// Resolve the series first, then pick the closest name among its characters.
+///
+async fn lookup_series_first(name: &String, series: &String) -> Option<Character> {
+    // A short read's regex matches (almost) every series
+    if is_short_read(series) {
+        return None;
+    }
+    let min_similarity = CONFIG.get().unwrap().analyzer.matching.min_similarity;
+    let series_list = db::query_series_regex(&regexify_text(series)).await.ok()?;
+    let best_series = series_list
+        .into_iter()
+        .map(|s| (similarity(series, &s), s))
+        .filter(|(score, _)| *score >= min_similarity)
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))?
+        .1;
+    trace!("Series first: resolved series to {}", best_series);
+    db::query_characters_in_series(&best_series)
+        .await
+        .ok()?
+        .into_iter()
+        .map(|c| (similarity(name, &c.name), c))
+        .filter(|(score, _)| *score >= min_similarity)
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        .map(|(_, c)| c)
+}
+
+///
+/// Resolve the name first, then pick the closest series among the characters with that name.
+///
+async fn lookup_name_first(name: &String, series: &String) -> Option<Character> {
+    if is_short_read(name) {
+        return None;
+    }
+    let matching = &CONFIG.get().unwrap().analyzer.matching;
+    db::query_characters_name_regex(&regexify_text(name), matching.max_candidates)
+        .await
+        .ok()?
+        .into_iter()
+        .map(|c| (similarity(series, &c.series), c))
+        .filter(|(score, _)| *score >= matching.min_similarity)
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        .map(|(_, c)| c)
+}
+
+///
+/// Look up the character in the database, or only wrap the OCR text if `lookup` is false.
+///
+async fn lookup_character(
+    name: String,
+    series: String,
+    series_confidence: f32,
+    lookup: bool,
+) -> (Character, MatchKind) {
+    let config = &CONFIG.get().unwrap().analyzer;
+    let character = Character {
+        wishlist: None,
+        name,
+        series,
+        last_update_ts: 0,
+    };
+    if !lookup {
+        return (character, MatchKind::NotFound);
+    }
+    // Read the wishlist number
+    if let Some(c) = db::query_character(&character.name, &character.series).await {
+        return (c, MatchKind::Exact);
+    }
+    if let Some(c) = db::query_character_regex(
+        &regexify_text(&character.name),
+        &regexify_text(&character.series),
+    )
+    .await
+    {
+        return (c, MatchKind::Regex);
+    }
+    // Constrained matching only when the combined regex found nothing, in the order
+    // the strategies are tried.
+    let strategies: &[MatchKind] = match config.matching.mode {
+        MatchingMode::Combined => &[],
+        MatchingMode::SeriesFirst => &[MatchKind::SeriesFirst],
+        MatchingMode::NameFirst => &[MatchKind::NameFirst],
+        MatchingMode::Auto => {
+            if series_confidence >= config.confidence.min_ocr_confidence {
+                &[MatchKind::SeriesFirst, MatchKind::NameFirst]
+            } else {
+                &[MatchKind::NameFirst, MatchKind::SeriesFirst]
             }
-        };
-        let mut leptess = binding.lock().unwrap();
-        let name_img = image_with_white_padding(card_clone.crop_imm(
-            CARD_NAME_X_OFFSET,
-            CARD_NAME_Y_OFFSET,
-            CARD_NAME_WIDTH,
-            CARD_NAME_HEIGHT,
-        ));
-        let mut buffer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        match name_img.write_to(&mut buffer, ImageFormat::Png) {
-            Ok(_) => {}
-            Err(why) => {
-                panic!("{}", format!("Failed to write image: {:?}", why));
+        }
+    };
+    for strategy in strategies {
+        let result = match strategy {
+            MatchKind::SeriesFirst => {
+                lookup_series_first(&character.name, &character.series).await
             }
+            _ => lookup_name_first(&character.name, &character.series).await,
         };
-        save_image_if_trace(
-            &name_img,
-            format!("debug/4-libtesseract-{}-name.png", count).as_str(),
-        );
-        leptess.set_image_from_mem(&buffer.get_mut()).unwrap();
-        let mut name_str = leptess.get_utf8_text().expect("Failed to read name");
-        fix_tesseract_string(&mut name_str);
-        name_str
-    });
-    let card_clone = card.clone();
-    let series_thread = task::spawn_blocking(move || {
-        // let mut leptess =
-        //     libtesseract::init_tesseract(false).expect("Failed to initialize Tesseract");
-        let binding = unsafe {
+        if let Some(c) = result {
+            return (c, *strategy);
+        }
+    }
+    (character, MatchKind::NotFound)
+}
+
+///
+/// Get a Tesseract instance with the image set.
+///
+fn libtesseract_with_image(img: &DynamicImage, numeric: bool, dpi: i32) -> Arc<Mutex<Engine>> {
+    let binding = unsafe {
+        if numeric {
+            libtesseract::get_tesseract_numeric()
+        } else {
             match libtesseract::get_tesseract() {
                 Ok(b) => b,
                 Err(why) => {
                     panic!("{}", format!("Failed to get Tesseract: {:?}", why));
                 }
             }
-        };
-        let mut leptess = binding.lock().unwrap();
-        let series_img = image_with_white_padding(card_clone.crop_imm(
-            CARD_SERIES_X_OFFSET,
-            CARD_SERIES_Y_OFFSET,
-            CARD_SERIES_WIDTH,
-            CARD_SERIES_HEIGHT,
-        ));
-        let mut buffer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        match series_img.write_to(&mut buffer, ImageFormat::Png) {
-            Ok(_) => {}
-            Err(why) => {
-                panic!("{}", format!("Failed to write image: {:?}", why));
-            }
-        };
-        save_image_if_trace(
-            &series_img,
-            format!("debug/4-libtesseract-{}-series.png", count).as_str(),
-        );
-        leptess.set_image_from_mem(&buffer.get_mut()).unwrap();
-        let mut series_str = leptess.get_utf8_text().expect("Failed to read series");
-        fix_tesseract_string(&mut series_str);
-        series_str
-    });
-    let name = match name_thread.await {
-        Ok(name) => name,
-        Err(why) => {
-            return Err(format!("Failed to read name: {:?}", why));
         }
     };
-    trace!("Name: {}", name);
-    let series = match series_thread.await {
-        Ok(series) => series,
+    let mut leptess = binding.lock().unwrap();
+    libtesseract::set_dpi(&mut leptess, dpi).unwrap();
+    let mut buffer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    match img.write_to(&mut buffer, ImageFormat::Png) {
+        Ok(_) => {}
         Err(why) => {
-            return Err(format!("Failed to read series: {:?}", why));
+            panic!("{}", format!("Failed to write image: {:?}", why));
         }
     };
-    trace!("Series: {}", series);
-    // TODO: Read the print number
-    let mut character = Character {
-        wishlist: None,
-        name,
-        series,
-        last_update_ts: 0,
+    leptess.set_image_from_mem(&buffer.get_mut()).unwrap();
+    drop(leptess);
+    binding
+}
+
+fn read_text_libtesseract(img: &DynamicImage, numeric: bool, dpi: i32) -> (String, OcrConfidence) {
+    let binding = libtesseract_with_image(img, numeric, dpi);
+    let mut leptess = binding.lock().unwrap();
+    libtesseract::get_text_with_confidence(&mut leptess).expect("Failed to read text")
+}
+
+fn read_text_subprocess(img: &DynamicImage, numeric: bool, dpi: i32) -> (String, OcrConfidence) {
+    if numeric {
+        subprocess::image_to_numeric_data(img, dpi).unwrap()
+    } else {
+        subprocess::image_to_data(img, dpi).unwrap()
+    }
+}
+
+///
+/// The DPI of the profile, scaled like the text by the preprocessing steps.
+///
+fn adapted_dpi(numeric: bool, steps: &[PreprocessStep]) -> i32 {
+    let config = &CONFIG.get().unwrap().tesseract;
+    let dpi = match numeric {
+        true => config.numeric.dpi,
+        false => config.text.dpi,
     };
-    // Read the wishlist number
-    match db::query_character(&character.name, &character.series).await {
-        Some(c) => {
-            character = c;
+    (dpi as f32 * preprocess::scale(steps)).round() as i32
+}
+
+///
+/// Read the words of the image with their position, using the text profile.
+///
+fn read_words(img: &DynamicImage, dpi: i32) -> Vec<OcrWord> {
+    let config = CONFIG.get().unwrap();
+    match config.tesseract.backend.as_str() {
+        "libtesseract" => {
+            let binding = libtesseract_with_image(img, false, dpi);
+            let mut leptess = binding.lock().unwrap();
+            libtesseract::get_words(&mut leptess).expect("Failed to read words")
         }
-        None => match db::query_character_regex(
-            &regexify_text(&character.name),
-            &regexify_text(&character.series),
-        )
-        .await
+        "subprocess" => subprocess::image_to_words(img, dpi).unwrap(),
+        _ => {
+            panic!("Invalid Tesseract backend: {}", config.tesseract.backend);
+        }
+    }
+}
+
+fn read_text(
+    img: DynamicImage,
+    steps: &[PreprocessStep],
+    numeric: bool,
+    capture: &Capture,
+    image_name: String,
+) -> (String, OcrConfidence) {
+    let config = CONFIG.get().unwrap();
+    let dpi = adapted_dpi(numeric, steps);
+    let img = preprocess::apply(img, steps);
+    if let Some(capture) = capture {
+        capture.save_image(&img, &image_name);
+    }
+    match config.tesseract.backend.as_str() {
+        "libtesseract" => read_text_libtesseract(&img, numeric, dpi),
+        "subprocess" => read_text_subprocess(&img, numeric, dpi),
+        _ => {
+            panic!("Invalid Tesseract backend: {}", config.tesseract.backend);
+        }
+    }
+}
+
+async fn read_text_region(
+    img: DynamicImage,
+    steps: &'static [PreprocessStep],
+    capture: Capture,
+    count: u32,
+    region: &'static str,
+    variant: &'static str,
+) -> Result<(String, OcrConfidence), CardError> {
+    let thread = task::spawn_blocking(move || {
+        let image_name = format!("4-{}-{}-{}.png", count, region, variant);
+        let (raw, confidence) = read_text(img, steps, false, &capture, image_name);
+        let mut text = raw.clone();
+        fix_tesseract_string(&mut text, region);
+        if let Some(capture) = capture {
+            capture.update_card(count, |c| {
+                c.reads.push(RegionRead {
+                    region: region.to_string(),
+                    variant: variant.to_string(),
+                    raw,
+                    text: text.clone(),
+                    confidence: confidence.mean,
+                })
+            });
+        }
+        (text, confidence)
+    });
+    let ocr_ms = CONFIG.get().unwrap().analyzer.timeouts.ocr_ms;
+    match with_timeout("ocr", ocr_ms, thread).await? {
+        Ok(result) => Ok(result),
+        Err(why) => Err(CardError::Failed(format!(
+            "Failed to read {}: {:?}",
+            region, why
+        ))),
+    }
+}
+
+///
+/// Read the name and series of all cards with a single Tesseract call.
+///
+/// Returns the (name, series) reads of each card, or `None` if the batch read failed
+/// and the cards have to be read on their own.
+///
+async fn read_cards_batch(
+    cards: &[DynamicImage],
+    layout: &CardLayout,
+    capture: &Capture,
+) -> Option<Vec<BatchRead>> {
+    let start = Instant::now();
+    let config = CONFIG.get().unwrap();
+    let (name_region, series_region) =
+        match (get_region(layout, "name"), get_region(layout, "series")) {
+            (Ok(name), Ok(series)) => (name, series),
+            (Err(why), _) | (_, Err(why)) => {
+                warn!("Failed to read cards in batch: {}", why);
+                return None;
+            }
+        };
+    let mut images: Vec<DynamicImage> = Vec::with_capacity(cards.len() * 2);
+    for card in cards {
+        images.push(crop_region(card, &name_region));
+        images.push(crop_region(card, &series_region));
+    }
+    let batch_capture = capture.clone();
+    let thread = task::spawn_blocking(move || {
+        let preprocess_steps = &config.analyzer.preprocess;
+        let images: Vec<DynamicImage> = images
+            .into_iter()
+            .enumerate()
+            .map(|(i, img)| match i % 2 {
+                0 => preprocess::apply(img, &preprocess_steps.name),
+                _ => preprocess::apply(img, &preprocess_steps.series),
+            })
+            .collect();
+        let (img, ranges) = batch::compose(&images, config.analyzer.batch.gap);
+        if let Some(capture) = batch_capture {
+            capture.save_image(&img, "4-batch.png");
+        }
+        // Names and series are stacked, so only one DPI can be used
+        let dpi = adapted_dpi(false, &preprocess_steps.name);
+        batch::split(&read_words(&img, dpi), &ranges)
+    });
+    let ocr_ms = config.analyzer.timeouts.ocr_ms;
+    let reads = match time::timeout(Duration::from_millis(ocr_ms), thread).await {
+        Ok(Ok(reads)) => reads,
+        Ok(Err(why)) => {
+            warn!("Failed to read cards in batch: {:?}", why);
+            return None;
+        }
+        Err(_) => {
+            warn!("Timed out reading cards in batch");
+            return None;
+        }
+    };
+    if let Some(capture) = capture {
+        capture.timing("batch-ocr", start.elapsed());
+    }
+    let mut result: Vec<BatchRead> = Vec::with_capacity(cards.len());
+    let mut reads = reads.into_iter();
+    for count in 0..cards.len() as u32 {
+        let mut pair: Vec<(String, OcrConfidence)> = Vec::with_capacity(2);
+        for region in ["name", "series"] {
+            let (raw, confidence) = reads.next()?;
+            let mut text = raw.clone();
+            fix_tesseract_string(&mut text, region);
+            if let Some(capture) = capture {
+                capture.update_card(count, |c| {
+                    c.reads.push(RegionRead {
+                        region: region.to_string(),
+                        variant: "batch".to_string(),
+                        raw,
+                        text: text.clone(),
+                        confidence: confidence.mean,
+                    })
+                });
+            }
+            pair.push((text, confidence));
+        }
+        let series = pair.pop()?;
+        let name = pair.pop()?;
+        result.push((name, series));
+    }
+    Some(result)
+}
+
+///
+/// Group the reads by their text and rank them by votes, then by confidence.
+///
+fn rank_candidates(candidates: &[(String, OcrConfidence)]) -> Vec<(String, OcrConfidence)> {
+    let mut ranked: Vec<(String, OcrConfidence, usize)> = Vec::new();
+    for (text, confidence) in candidates {
+        if text.is_empty() {
+            continue;
+        }
+        match ranked
+            .iter_mut()
+            .find(|(t, _, _)| t.to_lowercase() == text.to_lowercase())
         {
-            Some(c) => {
-                character = c;
+            Some((_, best_confidence, votes)) => {
+                *votes += 1;
+                if confidence.mean > best_confidence.mean {
+                    *best_confidence = confidence.clone();
+                }
             }
-            None => {}
+            None => ranked.push((text.clone(), confidence.clone(), 1)),
+        }
+    }
+    ranked.sort_by(|a, b| {
+        b.2.cmp(&a.2)
+            .then(b.1.mean.partial_cmp(&a.1.mean).unwrap_or(Ordering::Equal))
+    });
+    ranked
+        .into_iter()
+        .map(|(text, confidence, _)| (text, confidence))
+        .collect()
+}
+
+///
+/// Pick the best name and series from the reads, preferring the ones which
+/// resolve to a known character.
+///
+async fn vote_character(
+    names: &[(String, OcrConfidence)],
+    series: &[(String, OcrConfidence)],
+    lookup: bool,
+) -> (String, OcrConfidence, String, OcrConfidence) {
+    let names = rank_candidates(names);
+    let series = rank_candidates(series);
+    let known_candidates = if lookup { VOTE_CANDIDATES } else { 0 };
+    for name in names.iter().take(known_candidates) {
+        for series in series.iter().take(VOTE_CANDIDATES) {
+            if db::query_character(&name.0, &series.0).await.is_some() {
+                trace!("Voted for {} • {} (known character)", name.0, series.0);
+                return (
+                    name.0.clone(),
+                    name.1.clone(),
+                    series.0.clone(),
+                    series.1.clone(),
+                );
+            }
+        }
+    }
+    let (name, name_confidence) = names.into_iter().next().unwrap_or_default();
+    let (series, series_confidence) = series.into_iter().next().unwrap_or_default();
+    trace!("Voted for {} • {}", name, series);
+    (name, name_confidence, series, series_confidence)
+}
+
+async fn read_print(
+    thread: Option<task::JoinHandle<(String, OcrConfidence)>>,
+) -> Result<i32, CardError> {
+    let ocr_ms = CONFIG.get().unwrap().analyzer.timeouts.ocr_ms;
+    match thread {
+        Some(thread) => match with_timeout("ocr", ocr_ms, thread).await? {
+            Ok((print, _)) => Ok(parse_print(&print)),
+            Err(why) => Err(CardError::Failed(format!(
+                "Failed to read print: {:?}",
+                why
+            ))),
         },
+        None => Ok(0),
     }
-    Ok(DroppedCard {
-        character,
-        print: 0,
-        edition: 0,
-    })
 }
 
-pub async fn analyze_card_subprocess(
+///
+/// Look up the character of the closest card art, checking it's still in the database.
+///
+async fn lookup_fingerprint(hash: u64) -> Option<Character> {
+    let (fp, distance) = fingerprint::find(hash)?;
+    match db::query_character(&fp.name, &fp.series).await {
+        Some(character) => {
+            trace!(
+                "Fingerprint {:016x} matched {} • {} (distance: {})",
+                hash,
+                fp.name,
+                fp.series,
+                distance
+            );
+            Some(character)
+        }
+        None => {
+            trace!(
+                "Fingerprint matched {} • {} which is not in the database",
+                fp.name,
+                fp.series
+            );
+            None
+        }
+    }
+}
+
+pub async fn analyze_card(
     card: image::DynamicImage,
+    layout: CardLayout,
     count: u32,
-) -> Result<DroppedCard, String> {
+    deadline: Instant,
+    capture: Capture,
+    lookup: bool,
+    batch_read: Option<BatchRead>,
+) -> CardOutcome {
+    let start = Instant::now();
+    let name_region = get_region(&layout, "name")?;
+    let series_region = get_region(&layout, "series")?;
+    let analyzer_config = &CONFIG.get().unwrap().analyzer;
+    let preprocess_steps = &analyzer_config.preprocess;
+    let lookup_ms = analyzer_config.timeouts.lookup_ms;
     trace!("Spawning threads for analyzing card...");
-    // Read the name and the series
-    let card_clone = card.clone();
-    let name_thread = task::spawn_blocking(move || {
-        let name_img = image_with_white_padding(card_clone.crop_imm(
-            CARD_NAME_X_OFFSET,
-            CARD_NAME_Y_OFFSET,
-            CARD_NAME_WIDTH,
-            CARD_NAME_HEIGHT,
-        ));
-        let img = subprocess::Image::from_dynamic_image(&name_img).unwrap();
-        save_image_if_trace(
-            &name_img,
-            format!("debug/4-subprocess-{}-name.png", count).as_str(),
-        );
-        let mut name_str = subprocess::image_to_string(&img).unwrap();
-        fix_tesseract_string(&mut name_str);
-        name_str
-    });
-    let card_clone = card.clone();
-    let series_thread = task::spawn_blocking(move || {
-        let series_img = image_with_white_padding(card_clone.crop_imm(
-            CARD_SERIES_X_OFFSET,
-            CARD_SERIES_Y_OFFSET,
-            CARD_SERIES_WIDTH,
-            CARD_SERIES_HEIGHT,
-        ));
-        let img = subprocess::Image::from_dynamic_image(&series_img).unwrap();
-        save_image_if_trace(
-            &series_img,
-            format!("debug/4-subprocess-{}-series.png", count).as_str(),
-        );
-        let mut series_str = subprocess::image_to_string(&img).unwrap();
-        fix_tesseract_string(&mut series_str);
-        series_str
-    });
-    let name = match name_thread.await {
-        Ok(name) => name,
-        Err(why) => {
-            return Err(format!("Failed to read name: {:?}", why));
+    let name_img = crop_region(&card, &name_region);
+    let series_img = crop_region(&card, &series_region);
+    let print_thread = match layout.regions.get("print") {
+        Some(region) => {
+            let print_img = crop_region(&card, region);
+            let print_capture = capture.clone();
+            Some(task::spawn_blocking(move || {
+                read_text(
+                    print_img,
+                    &preprocess_steps.numeric,
+                    true,
+                    &print_capture,
+                    format!("4-{}-print-base.png", count),
+                )
+            }))
         }
+        None => None,
     };
-    trace!("Name: {}", name);
-    let series = match series_thread.await {
-        Ok(series) => series,
-        Err(why) => {
-            return Err(format!("Failed to read series: {:?}", why));
+    // Identify the card by its art first, only reading the name to check it
+    let art_hash = match layout.regions.get("art") {
+        Some(region) if lookup && analyzer_config.fingerprint.enabled => {
+            Some(fingerprint::dhash(&crop_region(&card, region)))
         }
+        _ => None,
     };
-    // TODO: Read the print number
-    let mut character = Character {
-        wishlist: None,
-        name,
-        series,
-        last_update_ts: 0,
-    };
-    // Read the wishlist number
-    match db::query_character(&character.name, &character.series).await {
-        Some(c) => {
-            character = c;
+    let mut checked_name: Option<(String, OcrConfidence)> = None;
+    if let Some(hash) = art_hash {
+        if let Some(capture) = &capture {
+            capture.update_card(count, |c| c.fingerprint = Some(format!("{:016x}", hash)));
         }
-        None => match db::query_character_regex(
-            &regexify_text(&character.name),
-            &regexify_text(&character.series),
-        )
-        .await
+        // A timed out fingerprint lookup is a miss
+        if let Ok(Some(character)) =
+            with_timeout("lookup", lookup_ms, lookup_fingerprint(hash)).await
         {
-            Some(c) => {
-                character = c;
+            let (name, name_confidence) = match &batch_read {
+                Some((name, _)) => name.clone(),
+                None => {
+                    read_text_region(
+                        name_img.clone(),
+                        &preprocess_steps.name,
+                        capture.clone(),
+                        count,
+                        "name",
+                        "base",
+                    )
+                    .await?
+                }
+            };
+            let name_similarity = similarity(&name, &character.name);
+            if name_similarity >= analyzer_config.confidence.min_match_similarity {
+                let print = read_print(print_thread).await?;
+                // The series isn't read, the art and the name vouch for it
+                let series_confidence = name_confidence.clone();
+                if let Some(capture) = &capture {
+                    capture.card_timing(count, "ocr", start.elapsed());
+                    capture.update_card(count, |c| {
+                        c.name = name.clone();
+                        c.name_confidence = name_confidence.clone();
+                        c.series_confidence = series_confidence.clone();
+                        c.match_kind = Some(MatchKind::Fingerprint);
+                        c.character = Some(character.clone());
+                        c.timings
+                            .insert("total".to_string(), start.elapsed().as_millis());
+                    });
+                }
+                return Ok(DroppedCard {
+                    character,
+                    print,
+                    edition: 0,
+                    name_confidence,
+                    series_confidence,
+                    match_kind: MatchKind::Fingerprint,
+                    match_similarity: name_similarity,
+                });
             }
-            None => {}
-        },
+            warn!(
+                "Fingerprint {:016x} matched {} but the name reads {}, forgetting it",
+                hash, character.name, name
+            );
+            fingerprint::forget(hash, &character);
+            checked_name = Some((name, name_confidence));
+        }
+    }
+    // Read the name and the series, unless the name was already read for the art
+    let (name_result, series_result) = match (batch_read, checked_name) {
+        (Some((name, series)), _) => (Ok(name), Ok(series)),
+        (None, Some(name)) => (
+            Ok(name),
+            read_text_region(
+                series_img.clone(),
+                &preprocess_steps.series,
+                capture.clone(),
+                count,
+                "series",
+                "base",
+            )
+            .await,
+        ),
+        (None, None) => tokio::join!(
+            read_text_region(
+                name_img.clone(),
+                &preprocess_steps.name,
+                capture.clone(),
+                count,
+                "name",
+                "base",
+            ),
+            read_text_region(
+                series_img.clone(),
+                &preprocess_steps.series,
+                capture.clone(),
+                count,
+                "series",
+                "base",
+            )
+        ),
+    };
+    let (name, mut name_confidence) = name_result?;
+    trace!("Name: {} (confidence: {})", name, name_confidence.mean);
+    let (series, mut series_confidence) = series_result?;
+    trace!("Series: {} (confidence: {})", series, series_confidence.mean);
+    let print = read_print(print_thread).await?;
+    trace!("Print: {}", print);
+    if let Some(capture) = &capture {
+        capture.card_timing(count, "ocr", start.elapsed());
+    }
+    let (mut ocr_name, mut ocr_series) = (name.clone(), series.clone());
+    let (mut character, mut match_kind) = with_timeout(
+        "lookup",
+        lookup_ms,
+        lookup_character(name, series, series_confidence.mean, lookup),
+    )
+    .await?;
+    if match_kind != MatchKind::Exact && analyzer_config.variants.enabled {
+        let mut name_candidates = vec![(ocr_name, name_confidence)];
+        let mut series_candidates = vec![(ocr_series, series_confidence)];
+        for variant in analyzer_config.variants.list.iter() {
+            if Instant::now() >= deadline {
+                trace!("Time budget exceeded, skipping remaining variants");
+                break;
+            }
+            trace!("Reading card {} with variant '{}'", count, variant.name);
+            let (name_result, series_result) = tokio::join!(
+                read_text_region(
+                    name_img.clone(),
+                    &variant.steps,
+                    capture.clone(),
+                    count,
+                    "name",
+                    &variant.name,
+                ),
+                read_text_region(
+                    series_img.clone(),
+                    &variant.steps,
+                    capture.clone(),
+                    count,
+                    "series",
+                    &variant.name,
+                )
+            );
+            match name_result {
+                Ok(name) => name_candidates.push(name),
+                Err(why) => warn!("Failed to read name with '{}': {}", variant.name, why),
+            }
+            match series_result {
+                Ok(series) => series_candidates.push(series),
+                Err(why) => warn!("Failed to read series with '{}': {}", variant.name, why),
+            }
+        }
+        (ocr_name, name_confidence, ocr_series, series_confidence) = with_timeout(
+            "lookup",
+            lookup_ms,
+            vote_character(&name_candidates, &series_candidates, lookup),
+        )
+        .await?;
+        (character, match_kind) = with_timeout(
+            "lookup",
+            lookup_ms,
+            lookup_character(
+                ocr_name.clone(),
+                ocr_series.clone(),
+                series_confidence.mean,
+                lookup,
+            ),
+        )
+        .await?;
     }
-    Ok(DroppedCard {
+    let match_similarity = match match_kind {
+        MatchKind::NotFound => 0.0,
+        _ => similarity(
+            &format!("{} {}", ocr_name, ocr_series),
+            &format!("{} {}", character.name, character.series),
+        ),
+    };
+    // Learn from the OCR mistakes of confidently resolved cards
+    if match_kind != MatchKind::NotFound
+        && match_kind != MatchKind::Exact
+        && match_similarity
+            >= CONFIG
+                .get()
+                .unwrap()
+                .analyzer
+                .confidence
+                .min_match_similarity
+    {
+        confusions::record(&ocr_name, &character.name);
+        confusions::record(&ocr_series, &character.series);
+    }
+    if let Some(capture) = &capture {
+        capture.update_card(count, |c| {
+            c.name = ocr_name;
+            c.series = ocr_series;
+            c.name_regex = regexify_text(&c.name);
+            c.series_regex = regexify_text(&c.series);
+            c.name_confidence = name_confidence.clone();
+            c.series_confidence = series_confidence.clone();
+            c.match_kind = Some(match_kind);
+            c.character = Some(character.clone());
+            c.timings
+                .insert("total".to_string(), start.elapsed().as_millis());
+        });
+    }
+    let card = DroppedCard {
         character,
-        print: 0,
+        print,
         edition: 0,
-    })
+        name_confidence,
+        series_confidence,
+        match_kind,
+        match_similarity,
+    };
+    if let Some(hash) = art_hash {
+        if card.match_kind != MatchKind::NotFound && !is_doubtful(&card) {
+            fingerprint::remember(hash, &card.character);
+        }
+    }
+    Ok(card)
 }
 
-async fn execute_analyze_drop(image: DynamicImage, count: u32) -> Result<DroppedCard, String> {
-    let config = CONFIG.get().unwrap();
-    match config.tesseract.backend.as_str() {
-        "libtesseract" => analyze_card_libtesseract(image, count).await,
-        "subprocess" => analyze_card_subprocess(image, count).await,
-        _ => {
-            panic!("Invalid Tesseract backend: {}", config.tesseract.backend);
+///
+/// Whether the card was read or matched poorly, so its information may be wrong.
+///
+pub fn is_doubtful(card: &DroppedCard) -> bool {
+    let thresholds = &CONFIG.get().unwrap().analyzer.confidence;
+    card.match_kind == MatchKind::NotFound
+        || card.name_confidence.mean < thresholds.min_ocr_confidence
+        || card.series_confidence.mean < thresholds.min_ocr_confidence
+        || card.match_similarity < thresholds.min_match_similarity
+}
+
+///
+/// Analyze the drop in the message, reusing the past analysis of the same image.
+///
+pub async fn analyze_drop_message(message: &Message) -> Result<Vec<CardOutcome>, String> {
+    let image = match find_drop_image(message) {
+        Some(image) => image,
+        None => return Err("No image attachment or embed found".to_string()),
+    };
+    let key = image.key();
+    // Duplicate events wait for the analysis in progress, then reuse it
+    let _in_flight = cache::lock(&key).await;
+    if let Some(cards) = cache::cards(&key) {
+        trace!("Using the cached analysis of drop {}", key);
+        return Ok(cards);
+    }
+    let capture = DropCapture::new(message);
+    let start = Instant::now();
+    let result = analyze_drop(message, image, &capture, drop_deadline()).await;
+    if let Ok(cards) = &result {
+        // Failed cards may succeed when analyzed again
+        if cards.iter().all(|card| card.is_ok()) {
+            cache::insert_cards(&key, cards);
+        }
+    }
+    if let Some(capture) = &capture {
+        capture.timing("total", start.elapsed());
+        if let Err(why) = &result {
+            capture.update(|m| m.error = Some(why.clone()));
+        }
+        capture.finish();
+    }
+    result
+}
+
+enum DropImage<'a> {
+    Attachment(&'a Attachment),
+    Embed(String),
+}
+
+impl DropImage<'_> {
+    ///
+    /// The cache key of the image, the attachment ID or the embed image URL.
+    ///
+    fn key(&self) -> String {
+        match self {
+            DropImage::Attachment(attachment) => attachment.id.to_string(),
+            DropImage::Embed(url) => url.clone(),
+        }
+    }
+}
+
+///
+/// Find the drop image, either the first image attachment or the first embed image (or thumbnail).
+///
+fn find_drop_image(message: &Message) -> Option<DropImage> {
+    let attachment = message.attachments.iter().find(|attachment| {
+        attachment.width.is_some()
+            || attachment
+                .content_type
+                .as_ref()
+                .map(|content_type| content_type.starts_with("image/"))
+                .unwrap_or(false)
+    });
+    if let Some(attachment) = attachment {
+        return Some(DropImage::Attachment(attachment));
+    }
+    for embed in message.embeds.iter() {
+        if let Some(image) = &embed.image {
+            return Some(DropImage::Embed(image.url.clone()));
+        }
+        if let Some(thumbnail) = &embed.thumbnail {
+            return Some(DropImage::Embed(thumbnail.url.clone()));
         }
     }
+    None
 }
 
-pub async fn analyze_drop_message(message: &Message) -> Result<Vec<DroppedCard>, String> {
-    if message.attachments.len() < 1 {
-        return Err("No attachments found".to_string());
+///
+/// Download the drop image, refusing images over the size or dimension limits.
+///
+async fn download_drop_image(image: &DropImage<'_>) -> Result<Vec<u8>, String> {
+    let limits = &CONFIG.get().unwrap().analyzer.downloads;
+    match image {
+        DropImage::Attachment(attachment) => {
+            if attachment.size as u64 > limits.max_bytes {
+                return Err(format!("Image is too large ({} bytes)", attachment.size));
+            }
+            if let (Some(width), Some(height)) = (attachment.width, attachment.height) {
+                if width > limits.max_width || height > limits.max_height {
+                    return Err(format!("Image is too large ({}x{})", width, height));
+                }
+            }
+            match attachment.download().await {
+                Ok(bytes) => Ok(bytes),
+                Err(why) => Err(format!("Failed to download attachment: {:?}", why)),
+            }
+        }
+        DropImage::Embed(url) => {
+            let mut response = match reqwest::get(url).await {
+                Ok(response) => response,
+                Err(why) => return Err(format!("Failed to download embed image: {:?}", why)),
+            };
+            if let Some(length) = response.content_length() {
+                if length > limits.max_bytes {
+                    return Err(format!("Image is too large ({} bytes)", length));
+                }
+            }
+            // The length header may be missing or wrong, so check while downloading too
+            let mut bytes: Vec<u8> = Vec::new();
+            loop {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => {
+                        bytes.extend_from_slice(&chunk);
+                        if bytes.len() as u64 > limits.max_bytes {
+                            return Err(format!(
+                                "Image is too large (over {} bytes)",
+                                limits.max_bytes
+                            ));
+                        }
+                    }
+                    Ok(None) => return Ok(bytes),
+                    Err(why) => return Err(format!("Failed to download embed image: {:?}", why)),
+                }
+            }
+        }
+    }
+}
+
+///
+/// Decode a drop image, detecting its format from the content.
+///
+/// PNG, JPEG, WebP and GIF (only the first frame) are supported, images over the
+/// dimension limits are rejected before decoding.
+///
+pub fn decode_image(bytes: &[u8]) -> Result<DynamicImage, String> {
+    let mut reader = match ImageReader::new(Cursor::new(bytes)).with_guessed_format() {
+        Ok(reader) => reader,
+        Err(why) => return Err(format!("Failed to read image: {:?}", why)),
     };
-    // Get the image attachment
-    let attachment = &message.attachments[0];
-    let image_bytes = match attachment.download().await {
-        Ok(bytes) => bytes,
-        Err(why) => return Err(format!("Failed to download attachment: {:?}", why)),
+    match reader.format() {
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Gif) => {}
+        Some(format) => return Err(format!("Unsupported image format: {:?}", format)),
+        None => return Err("Unknown image format".to_string()),
+    }
+    let downloads = &CONFIG.get().unwrap().analyzer.downloads;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(downloads.max_width);
+    limits.max_image_height = Some(downloads.max_height);
+    reader.limits(limits);
+    // GIFs are decoded as their first frame
+    match reader.decode() {
+        Ok(img) => Ok(img),
+        Err(why) => Err(format!("Failed to decode image: {:?}", why)),
+    }
+}
+
+async fn analyze_drop(
+    message: &Message,
+    image: DropImage<'_>,
+    capture: &Capture,
+    deadline: Instant,
+) -> Result<Vec<CardOutcome>, String> {
+    let key = image.key();
+    let img = match cache::image(&key) {
+        Some(img) => {
+            trace!("Using the cached image of drop {}", key);
+            img
+        }
+        None => {
+            let start = Instant::now();
+            let download_ms = CONFIG.get().unwrap().analyzer.timeouts.download_ms;
+            let image_bytes = match time::timeout(
+                Duration::from_millis(download_ms),
+                download_drop_image(&image),
+            )
+            .await
+            {
+                Ok(result) => result?,
+                Err(_) => return Err("Timed out downloading the image".to_string()),
+            };
+            if let Some(capture) = capture {
+                capture.timing("download", start.elapsed());
+            }
+            let img = decode_image(&image_bytes)?;
+            cache::insert_image(&key, &img);
+            img
+        }
     };
-    // Pre-process the image
-    let mut img =
-        match ImageReader::with_format(Cursor::new(image_bytes), ImageFormat::Png).decode() {
-            Ok(img) => img,
-            Err(why) => return Err(format!("Failed to decode image: {:?}", why)),
-        };
-    trace!("Grayscaling image...");
-    img = img.grayscale();
-    save_image_if_trace(&img, "debug/1-grayscale.png");
-    trace!("Increasing contrast of the image...");
-    contrast_in_place(&mut img, 127.0 / 4.0);
-    save_image_if_trace(&img, "debug/2-contrast.png");
+    analyze_drop_image(img, bot_name(message), capture, true, deadline).await
+}
+
+///
+/// Analyze an already decoded drop image.
+///
+/// If `lookup` is false the database is never queried and the cards only contain the OCR text.
+///
+/// Cards which aren't done by the deadline are cancelled and reported as timed out.
+///
+pub async fn analyze_drop_image(
+    mut img: DynamicImage,
+    bot: &str,
+    capture: &Capture,
+    lookup: bool,
+    drop_deadline: Instant,
+) -> Result<Vec<CardOutcome>, String> {
+    let start = Instant::now();
+    let bounds = normalize::find_cards(&img);
+    let layout = match select_layout(&CONFIG.get().unwrap().analyzer.layouts, bot, |layout| {
+        normalize::normalized_size(&img, bounds, layout)
+    }) {
+        Some(layout) => layout.clone(),
+        None => {
+            return Err(format!(
+                "No card layout found for {} ({}x{})",
+                bot,
+                img.width(),
+                img.height()
+            ))
+        }
+    };
+    trace!("Using card layout '{}'", layout.name);
+    trace!("Preprocessing image...");
+    if let Some(capture) = capture {
+        capture.update(|m| m.layout = Some(layout.name.clone()));
+        capture.save_image(&img, "1-original.png");
+    }
+    let (width, height) = (img.width(), img.height());
+    img = normalize::normalize(img, bounds, &layout);
+    if (img.width(), img.height()) != (width, height) {
+        if let Some(capture) = capture {
+            capture.save_image(&img, "1-normalized.png");
+        }
+    }
+    img = preprocess::apply(img, &CONFIG.get().unwrap().analyzer.preprocess.image);
+    if let Some(capture) = capture {
+        capture.save_image(&img, "2-preprocessed.png");
+        capture.timing("preprocess", start.elapsed());
+    }
     // Cropping cards
-    let distance = 257 - 29 + 305 - 259;
-    let cards_count = img.width() / distance;
+    let cards_count = img.width() / layout.stride;
     trace!("Cropping {} cards...", cards_count);
-    let mut jobs: Vec<_> = Vec::new();
-    let mut cards: Vec<DroppedCard> = Vec::with_capacity(cards_count.try_into().unwrap());
+    let deadline = (Instant::now()
+        + Duration::from_millis(CONFIG.get().unwrap().analyzer.variants.time_budget_ms))
+    .min(drop_deadline);
+    let mut card_imgs: Vec<DynamicImage> = Vec::with_capacity(cards_count as usize);
     for index in 0..cards_count {
         let i = index.clone();
-        let x = 29 + distance * i;
-        let y = 34;
-        let width = 257 + distance * i - x;
-        let height = 387 - y;
+        let x = layout.margin_left + layout.stride * i;
+        let y = layout.margin_top;
+        let width = layout.card_width;
+        let height = layout.card_height;
         trace!("Cropping card {} ({}, {}, {}, {})", i, x, y, width, height);
         let card_img = img.crop_imm(x, y, width, height);
-        save_image_if_trace(&card_img, &format!("debug/3-cropped-{}.png", i));
+        if let Some(capture) = capture {
+            capture.save_image(&card_img, &format!("3-cropped-{}.png", i));
+        }
+        card_imgs.push(card_img);
+    }
+    let batch_reads = match CONFIG.get().unwrap().analyzer.batch.enabled {
+        true => read_cards_batch(&card_imgs, &layout, capture).await,
+        false => None,
+    };
+    let mut jobs: Vec<_> = Vec::new();
+    let mut cards: Vec<CardOutcome> = Vec::with_capacity(cards_count.try_into().unwrap());
+    for (i, card_img) in card_imgs.into_iter().enumerate() {
+        let i = i as u32;
+        let card_layout = layout.clone();
+        let card_capture = capture.clone();
+        let batch_read = batch_reads.as_ref().map(|reads| reads[i as usize].clone());
         jobs.push(async move {
             trace!("Analyzing card {}", i);
-            (i, execute_analyze_drop(card_img, i).await)
+            analyze_card(
+                card_img,
+                card_layout,
+                i,
+                deadline,
+                card_capture,
+                lookup,
+                batch_read,
+            )
+            .await
         });
     }
-    let mut handles: Vec<task::JoinHandle<(u32, Result<DroppedCard, String>)>> = Vec::new();
+    let mut handles: Vec<task::JoinHandle<CardOutcome>> = Vec::new();
     for job in jobs {
         let handle = task::spawn(job);
         handles.push(handle);
     }
-    for handle in handles {
-        let result = handle.await;
-        match result {
-            Ok((i, card_result)) => {
-                let card = match card_result {
-                    Ok(card) => card,
-                    Err(why) => return Err(format!("Failed to analyze card: {}", why)),
-                };
-                trace!("Finished analyzing card {}", i);
-                cards.push(card);
+    for (i, mut handle) in handles.into_iter().enumerate() {
+        let i = i as u32;
+        // A panicking card (e.g. in OCR) only fails that card
+        let result = match time::timeout_at(drop_deadline, &mut handle).await {
+            Ok(Ok(card_result)) => card_result,
+            Ok(Err(why)) => Err(CardError::Failed(format!(
+                "Failed to analyze card: {:?}",
+                why
+            ))),
+            Err(_) => {
+                handle.abort();
+                Err(CardError::TimedOut("drop"))
             }
-            Err(why) => return Err(format!("Failed to analyze card: {:?}", why)),
         };
+        match &result {
+            Ok(_) => trace!("Finished analyzing card {}", i),
+            Err(why) => {
+                error!("Failed to analyze card {}: {}", i, why);
+                if let Some(capture) = capture {
+                    capture.update_card(i, |c| c.error = Some(why.to_string()));
+                }
+            }
+        }
+        cards.push(result);
     }
     Ok(cards)
 }
 
+///
+/// Format the analyzed cards for the drop reply.
+///
+/// Cards which were read or matched poorly are marked with a warning sign.
+///
+pub fn format_cards(cards: &[CardOutcome], verbose: bool) -> String {
+    let mut reply_str = String::new();
+    let mut has_doubtful = false;
+    for (i, card) in cards.iter().enumerate() {
+        let card = match card {
+            Ok(card) => card,
+            Err(CardError::TimedOut(stage)) => {
+                reply_str.push_str(&format!(
+                    ":hourglass: Card {}: ran out of time (`{}`)\n",
+                    i + 1,
+                    stage
+                ));
+                continue;
+            }
+            Err(why) => {
+                reply_str.push_str(&format!(":x: Card {}: `{}`\n", i + 1, why));
+                continue;
+            }
+        };
+        let wishlist_str: String = match card.character.wishlist {
+            Some(wishlist) => {
+                let mut out_str = wishlist.to_string();
+                while out_str.len() < 5 {
+                    out_str.push(' ');
+                }
+                out_str
+            }
+            None => "None ".to_string(),
+        };
+        let last_update_ts_str = match card.character.last_update_ts {
+            0 => "`Never`".to_string(),
+            ts => {
+                format!("<t:{}:R>", ts.to_string())
+            }
+        };
+        let icon = match is_doubtful(card) {
+            true => {
+                has_doubtful = true;
+                ":warning:"
+            }
+            false => ":heart:",
+        };
+        reply_str.push_str(
+            format!(
+                "{} `{}` • `{}` • **{}** • {} • {}\n",
+                icon,
+                wishlist_str,
+                card.print,
+                card.character.name,
+                card.character.series,
+                last_update_ts_str
+            )
+            .as_str(),
+        );
+        if verbose {
+            reply_str.push_str(
+                format!(
+                    "-# Name: `{:.1}%` • Series: `{:.1}%` • Match: `{:?}` (`{:.0}%`)\n",
+                    card.name_confidence.mean,
+                    card.series_confidence.mean,
+                    card.match_kind,
+                    card.match_similarity * 100.0
+                )
+                .as_str(),
+            );
+        }
+    }
+    if has_doubtful {
+        reply_str.push_str(":warning: = low confidence, the information may be wrong\n");
+    }
+    reply_str
+}
+
 pub async fn handle_drop_message(ctx: &Context, msg: &Message) {
     let start = Instant::now();
     match analyze_drop_message(msg).await {
         Ok(cards) => {
             let duration = start.elapsed();
-            let mut reply_str = String::new();
-            for card in cards {
-                // reply_str.push_str(&format!("{:?}\n", card));
-                let wishlist_str: String = match card.character.wishlist {
-                    Some(wishlist) => {
-                        let mut out_str = wishlist.to_string();
-                        while out_str.len() < 5 {
-                            out_str.push(' ');
-                        }
-                        out_str
-                    }
-                    None => "None ".to_string(),
-                };
-                let last_update_ts_str = match card.character.last_update_ts {
-                    0 => "`Never`".to_string(),
-                    ts => {
-                        format!("<t:{}:R>", ts.to_string())
-                    }
-                };
-                reply_str.push_str(
-                    format!(
-                        ":heart: `{}` • `{}` • **{}** • {} • {}\n",
-                        wishlist_str,
-                        card.print,
-                        card.character.name,
-                        card.character.series,
-                        last_update_ts_str
-                    )
-                    .as_str(),
-                )
-            }
+            let mut reply_str = format_cards(&cards, false);
             reply_str.push_str(&format!("Time taken (to analyze): `{:?}`", duration));
             match msg.reply(ctx, reply_str).await {
                 Ok(_) => {}
@@ -0,0 +1,46 @@
+use crate::config::Config;
+use crate::tesseract::{confusions, corrections, libtesseract, worker};
+use swordfish_common::info;
+use tokio::sync::OnceCell;
+
+pub mod cache;
+pub mod capture;
+pub mod config;
+pub mod fingerprint;
+pub mod helper;
+pub mod katana;
+pub mod normalize;
+pub mod preprocess;
+pub mod regression;
+pub mod template;
+pub mod tesseract;
+
+pub static CONFIG: OnceCell<Config> = OnceCell::const_new();
+
+///
+/// Initialize the Tesseract backend and load the OCR correction data.
+///
+/// Missing correction data files are created with the defaults if `create_files` is true.
+/// The config must be registered before calling this.
+///
+pub async fn init_analyzer(create_files: bool) {
+    let config = CONFIG.get().unwrap();
+    if config.tesseract.backend == "libtesseract" {
+        info!("Using libtesseract as Tesseract backend");
+        info!("Initializing libtesseract...");
+        libtesseract::init().await;
+    } else {
+        info!("Using subprocess as Tesseract backend");
+        if !config.tesseract.tessdata.is_empty() {
+            // The Tesseract command has no tessdata argument in rusty-tesseract.
+            std::env::set_var("TESSDATA_PREFIX", &config.tesseract.tessdata);
+        }
+        if config.tesseract.workers.enabled {
+            worker::init();
+        }
+    }
+    corrections::init(&config.analyzer.corrections.path, create_files)
+        .expect("Failed to load correction rules");
+    confusions::init(&config.analyzer.confusions.path, create_files)
+        .expect("Failed to load confusions");
+}
@@ -1,6 +1,6 @@
 #![feature(lazy_cell)]
 use dotenvy::dotenv;
-use serenity::all::{Embed, MessageUpdateEvent};
+use serenity::all::MessageUpdateEvent;
 use serenity::async_trait;
 use serenity::framework::standard::macros::{command, group};
 use serenity::framework::standard::{CommandResult, Configuration, StandardFramework};
@@ -10,21 +10,13 @@ use serenity::prelude::*;
 use std::env;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
+use swordfish::config::{self, Config};
+use swordfish::{fingerprint, helper, katana, regression, tesseract, CONFIG};
 use swordfish_common::*;
-use tokio::sync::OnceCell;
 
-use crate::config::Config;
-use crate::tesseract::libtesseract;
-
-mod config;
 mod debug;
-mod helper;
-mod katana;
-mod template;
-mod tesseract;
 
 const GITHUB_URL: &str = "https://github.com/teppyboy/swordfish";
-static CONFIG: OnceCell<Config> = OnceCell::const_new();
 
 #[group]
 #[commands(ping, debug, info)]
@@ -64,84 +56,10 @@ impl EventHandler for Handler {
             }
         };
         trace!("Message update: {}, sender: {}", content, author.id);
-        match author.id.get() {
-            constants::KATANA_ID => {
-                parse_katana_event(&ctx, event).await.unwrap();
-            }
-            constants::QINGQUE_ID => {
-                parse_qingque_event(&ctx, event).await.unwrap();
-            }
-            constants::CALF_ID => {
-                parse_calf_event(&ctx, event).await.unwrap();
-            }
-            _ => {}
-        }
-    }
-}
-
-async fn parse_calf_event(ctx: &Context, event: MessageUpdateEvent) -> Result<(), String> {
-    if event.content.is_none() {
-        return Ok(());
-    }
-    let content = event.content.unwrap();
-    if content.contains("Apricot v6 Drop Analysis Engine") {
-        let cards = utils::katana::parse_cards_from_calf_analysis(&content);
-        if cards.len() == 0 {
-            return Ok(());
-        }
-        debug!("Importing cards from Calf Analysis");
-        match database::katana::write_characters(cards).await {
-            Ok(_) => {
-                debug!("Imported successully");
-            }
-            Err(why) => {
-                error!("Failed to import card: {:?}", why);
-            }
-        }
-    }
-    Ok(())
-}
-
-async fn parse_qingque_event(ctx: &Context, event: MessageUpdateEvent) -> Result<(), String> {
-    if event.embeds.is_none() || event.embeds.clone().unwrap().len() == 0 {
-        return Ok(());
-    }
-    let embed = &event.embeds.unwrap()[0];
-    let embed_title = match embed.title {
-        Some(ref title) => title,
-        None => {
-            return Ok(());
-        }
-    };
-    match embed_title.as_str() {
-        "Top Wishlist" => {
-            let cards = utils::katana::parse_cards_from_qingque_atopwl(
-                &embed.description.as_ref().unwrap(),
-            );
-            debug!("Importing cards from Qingque 'Top Wishlist'");
-            match database::katana::write_characters(cards).await {
-                Ok(_) => {
-                    debug!("Imported successully");
-                }
-                Err(why) => {
-                    error!("Failed to import card: {:?}", why);
-                }
-            }
-        }
-        _ => {
-            return Ok(());
-        }
-    }
-    Ok(())
-}
-
-async fn parse_katana_event(ctx: &Context, event: MessageUpdateEvent) -> Result<(), String> {
-    if event.embeds.is_none() || event.embeds.clone().unwrap().len() == 0 {
-        return Ok(());
+        parsers::registry()
+            .dispatch(&helper::message_update_data(&event))
+            .await;
     }
-    let embed = &event.embeds.unwrap()[0];
-    parse_katana_embed(embed).await;
-    Ok(())
 }
 
 async fn parse_katana(ctx: &Context, msg: &Message) -> Result<(), String> {
@@ -162,88 +80,13 @@ async fn parse_katana(ctx: &Context, msg: &Message) -> Result<(), String> {
         }
         katana::handle_drop_message(ctx, msg).await;
     } else {
-        if msg.embeds.len() == 0 {
-            return Ok(());
-        }
-        let embed = &msg.embeds[0];
-        parse_katana_embed(embed).await;
+        parsers::registry()
+            .dispatch(&helper::message_data(msg))
+            .await;
     }
     Ok(())
 }
 
-async fn parse_katana_embed(embed: &Embed) {
-    match embed.author {
-        Some(ref author) => match author.name.as_str() {
-            "Card Collection" => {
-                let cards = utils::katana::parse_cards_from_katana_kc_ow(
-                    &embed.description.as_ref().unwrap(),
-                );
-                if cards.len() == 0 {
-                    return;
-                }
-                debug!("Importing cards from Katana 'Card Collection'");
-                match database::katana::write_characters(cards).await {
-                    Ok(_) => {
-                        debug!("Imported successully");
-                    }
-                    Err(why) => {
-                        error!("Failed to import card: {:?}", why);
-                    }
-                }
-            }
-            _ => {}
-        },
-        None => {}
-    };
-    match embed.title {
-        Some(ref title) => match title.as_str() {
-            "Character Lookup" => {
-                let card = match utils::katana::parse_cards_from_katana_klu_lookup(
-                    &embed.description.as_ref().unwrap(),
-                ) {
-                    Some(card) => card,
-                    None => {
-                        return;
-                    }
-                };
-                debug!("Importing a card from Katana 'Character Lookup'");
-                match database::katana::write_character(card).await {
-                    Ok(_) => {
-                        debug!("Imported successully");
-                    }
-                    Err(why) => {
-                        error!("Failed to import card: {:?}", why);
-                    }
-                }
-            }
-            "Character Results" => {
-                let fields = match embed.fields.len() {
-                    0 => {
-                        return;
-                    }
-                    _ => &embed.fields,
-                };
-                let embed_field = fields.get(0).unwrap();
-                let cards = utils::katana::parse_cards_from_katana_klu_results(&embed_field.value);
-                if cards.len() == 0 {
-                    return;
-                }
-                debug!("Importing cards from Katana 'Character Results'");
-                match database::katana::write_characters(cards).await {
-                    Ok(_) => {
-                        debug!("Imported successully");
-                    }
-                    Err(why) => {
-                        error!("Failed to import card: {:?}", why);
-                    }
-                }
-            }
-            _ => {}
-        },
-        None => {}
-    };
-}
-
 #[tokio::main(flavor = "multi_thread", worker_threads = 32)]
 async fn main() {
     match dotenv() {
@@ -252,7 +95,6 @@ async fn main() {
             eprintln!("Failed to load .env: {:?}", why);
         }
     }
-    let token = env::var("DISCORD_TOKEN").expect("Token not found");
     let config: Config;
     if Path::new("./config.toml").exists() {
         config = config::Config::load("./config.toml");
@@ -272,15 +114,24 @@ async fn main() {
     if config.log.file.enabled {
         info!("Logging to file: {}", CONFIG.get().unwrap().log.file.path);
     }
-    if config.tesseract.backend == "libtesseract" {
-        info!("Using libtesseract as Tesseract backend");
-        info!("Initializing libtesseract...");
-        libtesseract::init().await;
-    } else {
-        info!("Using subprocess as Tesseract backend");
+    swordfish::init_analyzer(true).await;
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("regression") {
+        std::process::exit(regression::run(&args[2..]).await);
     }
+    let token = env::var("DISCORD_TOKEN").expect("Token not found");
     info!("Initializing database...");
     swordfish_common::database::init().await;
+    if config.analyzer.fingerprint.enabled {
+        info!("Loading card art fingerprints...");
+        fingerprint::init().await;
+    }
+    if config.analyzer.dictionary.enabled {
+        tesseract::dictionary::init();
+    }
+    if config.analyzer.confusions.learn {
+        tesseract::confusions::init_saving();
+    }
     info!("Initializing Discord client...");
     let framework = StandardFramework::new().group(&GENERAL_GROUP);
     framework.configure(Configuration::new().prefix(config.general.prefix.clone()));
@@ -349,9 +200,11 @@ async fn debug(ctx: &Context, msg: &Message) -> CommandResult {
         "regexify-text" => debug::dbg_regexify_text(ctx, msg).await?,
         "regextxt" => debug::dbg_regexify_text(ctx, msg).await?,
         "parse-qingque-atopwl" => debug::dbg_parse_qingque_atopwl(ctx, msg).await?,
-        "parse-katana-kc_ow" => debug::dbg_parse_katana_kc_ow(ctx, msg).await?,
+        "parse-katana-kc" => debug::dbg_parse_katana_kc(ctx, msg).await?,
+        "parse-katana-kc_ow" => debug::dbg_parse_katana_kc(ctx, msg).await?,
         "parse-katana-klu_lookup" => debug::dbg_parse_katana_klu_lookup(ctx, msg).await?,
         "parse-katana-klu_results" => debug::dbg_parse_katana_klu_results(ctx, msg).await?,
+        "parse-katana-kv" => debug::dbg_parse_katana_card_view(ctx, msg).await?,
         "parse-calf-analysis" => debug::dbg_parse_calf_analysis(ctx, msg).await?,
         _ => {
             helper::error_message(
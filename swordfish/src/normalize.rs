@@ -0,0 +1,188 @@
+use crate::config::CardLayout;
+use crate::CONFIG;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use swordfish_common::{debug, trace};
+
+// How far (in pixels) the cards may be from the layout margins to count as not moved.
+const POSITION_TOLERANCE: u32 = 4;
+
+fn is_content(pixel: &Rgba<u8>, background: &Rgba<u8>, threshold: u8) -> bool {
+    if pixel[3] < 128 {
+        return false;
+    }
+    // Opaque pixels on a transparent drop are cards
+    if background[3] < 128 {
+        return true;
+    }
+    (0..3).any(|i| pixel[i].abs_diff(background[i]) > threshold)
+}
+
+///
+/// The first and last index of the longest run of `true`.
+///
+fn longest_run(values: &[bool]) -> Option<(usize, usize)> {
+    let mut longest: Option<(usize, usize)> = None;
+    let mut start: Option<usize> = None;
+    // The extra false ends a run reaching the end
+    for (i, value) in values.iter().chain([false].iter()).enumerate() {
+        match (value, start) {
+            (true, None) => start = Some(i),
+            (false, Some(first)) => {
+                let is_longest = match longest {
+                    Some((a, b)) => i - first > b - a + 1,
+                    None => true,
+                };
+                if is_longest {
+                    longest = Some((first, i - 1));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    longest
+}
+
+///
+/// Find the bounding box (x, y, width, height) of the cards.
+///
+/// The cards are the tallest band of rows which mostly differ from the background, so
+/// chat messages above and below them in screenshots are left out. Their columns are the
+/// ones mostly differing from the background within that band.
+///
+/// The background is the top-left pixel, transparent in drops sent by the bot and
+/// the chat background in screenshots.
+///
+fn card_bounds(img: &RgbaImage, threshold: u8) -> Option<(u32, u32, u32, u32)> {
+    let background = *img.get_pixel(0, 0);
+    let mut rows = vec![0u32; img.height() as usize];
+    for (_, y, pixel) in img.enumerate_pixels() {
+        if is_content(pixel, &background, threshold) {
+            rows[y as usize] += 1;
+        }
+    }
+    // Cards cover most of their rows and columns, stray text or borders don't
+    let content_rows: Vec<bool> = rows.iter().map(|count| count * 3 >= img.width()).collect();
+    let (top, bottom) = longest_run(&content_rows)?;
+    let band_height = (bottom - top + 1) as u32;
+    let mut columns = vec![0u32; img.width() as usize];
+    for y in top..=bottom {
+        for x in 0..img.width() {
+            if is_content(img.get_pixel(x, y as u32), &background, threshold) {
+                columns[x as usize] += 1;
+            }
+        }
+    }
+    let content_columns: Vec<usize> = (0..columns.len())
+        .filter(|x| columns[*x] * 2 >= band_height)
+        .collect();
+    let (left, right) = (*content_columns.first()?, *content_columns.last()?);
+    Some((
+        left as u32,
+        top as u32,
+        (right - left + 1) as u32,
+        band_height,
+    ))
+}
+
+///
+/// The bounding box (x, y, width, height) of the cards in the drop, or `None` if
+/// normalization is disabled or no cards were found.
+///
+pub fn find_cards(img: &DynamicImage) -> Option<(u32, u32, u32, u32)> {
+    let config = &CONFIG.get().unwrap().analyzer.normalize;
+    if !config.enabled {
+        return None;
+    }
+    let bounds = card_bounds(&img.to_rgba8(), config.background_threshold);
+    if bounds.is_none() {
+        trace!("No cards found to normalize the drop");
+    }
+    bounds
+}
+
+///
+/// The width of the cards once scaled to the layout, their count and the scale.
+///
+/// Returns `None` if the cards already match the layout or can't be fitted to it.
+///
+fn rescaling(bounds: (u32, u32, u32, u32), layout: &CardLayout) -> Option<(u32, u32, f32)> {
+    let tolerance = CONFIG.get().unwrap().analyzer.normalize.tolerance;
+    let (x, y, width, height) = bounds;
+    let scale = height as f32 / layout.card_height as f32;
+    if (scale - 1.0).abs() <= tolerance
+        && x.abs_diff(layout.margin_left) <= POSITION_TOLERANCE
+        && y.abs_diff(layout.margin_top) <= POSITION_TOLERANCE
+    {
+        return None;
+    }
+    let scaled_width = (width as f32 / scale).round() as u32;
+    let cards = ((scaled_width as f32 - layout.card_width as f32) / layout.stride as f32).round();
+    if cards < 0.0 {
+        trace!("Drop cards are narrower than a single card, not normalizing");
+        return None;
+    }
+    Some((scaled_width, cards as u32 + 1, scale))
+}
+
+///
+/// The size of the drop image once normalized to the layout, given its card bounds.
+///
+pub fn normalized_size(
+    img: &DynamicImage,
+    bounds: Option<(u32, u32, u32, u32)>,
+    layout: &CardLayout,
+) -> (u32, u32) {
+    match bounds.and_then(|bounds| rescaling(bounds, layout)) {
+        Some((_, cards, _)) => (
+            layout.margin_left + layout.stride * cards,
+            layout.margin_top * 2 + layout.card_height,
+        ),
+        None => (img.width(), img.height()),
+    }
+}
+
+///
+/// Rescale and reposition the cards of a scaled or cropped drop image (e.g. a screenshot)
+/// so they're where the layout expects them, given their bounds from `find_cards`.
+///
+/// Images already matching the layout are returned unchanged.
+///
+pub fn normalize(
+    img: DynamicImage,
+    bounds: Option<(u32, u32, u32, u32)>,
+    layout: &CardLayout,
+) -> DynamicImage {
+    let (x, y, width, height) = match bounds {
+        Some(bounds) => bounds,
+        None => return img,
+    };
+    let (scaled_width, cards, scale) = match rescaling((x, y, width, height), layout) {
+        Some(rescaling) => rescaling,
+        None => return img,
+    };
+    debug!(
+        "Normalizing drop with {} cards at ({}, {}) scaled by {:.2}",
+        cards, x, y, scale
+    );
+    let rgba = img.to_rgba8();
+    let content = imageops::resize(
+        &imageops::crop_imm(&rgba, x, y, width, height).to_image(),
+        scaled_width,
+        layout.card_height,
+        FilterType::Lanczos3,
+    );
+    let mut canvas = RgbaImage::from_pixel(
+        layout.margin_left + layout.stride * cards,
+        layout.margin_top * 2 + layout.card_height,
+        *rgba.get_pixel(0, 0),
+    );
+    imageops::overlay(
+        &mut canvas,
+        &content,
+        layout.margin_left as i64,
+        layout.margin_top as i64,
+    );
+    DynamicImage::ImageRgba8(canvas)
+}
@@ -0,0 +1,98 @@
+use crate::config::{PreprocessStep, ResizeFilter};
+use image::imageops::colorops::contrast_in_place;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba};
+use imageproc::contrast::{adaptive_threshold, otsu_level, threshold, ThresholdType};
+use imageproc::filter::median_filter;
+use swordfish_common::trace;
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> FilterType {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+pub fn image_with_white_padding(im: DynamicImage, size: u32) -> DynamicImage {
+    // Partially copied from https://github.com/PureSci/nori/blob/main/rust-workers/src/drop.rs#L102C1-L121C6
+    let mut new_im: DynamicImage =
+        ImageBuffer::<Rgba<u8>, Vec<u8>>::new(im.width() + size * 2, im.height() + size * 2)
+            .into();
+    let white = Rgba([255, 255, 255, 255]);
+    for y in 0..im.height() {
+        for x in 0..im.width() {
+            let p = im.get_pixel(x, y);
+            new_im.put_pixel(x + size, y + size, p.to_owned());
+        }
+    }
+    for y in 0..size {
+        for x in 0..im.width() + size * 2 {
+            new_im.put_pixel(x, y, white);
+            new_im.put_pixel(x, y + im.height() + size, white);
+        }
+    }
+    for x in 0..size {
+        for y in size..im.height() + size {
+            new_im.put_pixel(x, y, white);
+            new_im.put_pixel(x + im.width() + size, y, white);
+        }
+    }
+    new_im
+}
+
+///
+/// How much the preprocessing steps scale the image, so the OCR DPI can follow it.
+///
+pub fn scale(steps: &[PreprocessStep]) -> f32 {
+    steps
+        .iter()
+        .map(|step| match *step {
+            PreprocessStep::Upscale { factor, .. } => factor,
+            _ => 1.0,
+        })
+        .product()
+}
+
+///
+/// Apply the preprocessing steps to the image in order.
+///
+pub fn apply(mut img: DynamicImage, steps: &[PreprocessStep]) -> DynamicImage {
+    for step in steps {
+        trace!("Applying preprocessing step: {:?}", step);
+        img = match *step {
+            PreprocessStep::Grayscale => img.grayscale(),
+            PreprocessStep::Contrast { value } => {
+                contrast_in_place(&mut img, value);
+                img
+            }
+            PreprocessStep::OtsuThreshold => {
+                let gray = img.to_luma8();
+                let level = otsu_level(&gray);
+                DynamicImage::ImageLuma8(threshold(&gray, level, ThresholdType::Binary))
+            }
+            PreprocessStep::AdaptiveThreshold { block_radius } => {
+                DynamicImage::ImageLuma8(adaptive_threshold(&img.to_luma8(), block_radius))
+            }
+            PreprocessStep::Invert => {
+                img.invert();
+                img
+            }
+            PreprocessStep::Upscale { factor, filter } => {
+                let width = (img.width() as f32 * factor).round() as u32;
+                let height = (img.height() as f32 * factor).round() as u32;
+                img.resize_exact(width, height, filter.into())
+            }
+            PreprocessStep::Sharpen { sigma, threshold } => img.unsharpen(sigma, threshold),
+            PreprocessStep::MedianDenoise { radius } => {
+                DynamicImage::ImageRgba8(median_filter(&img.to_rgba8(), radius, radius))
+            }
+            PreprocessStep::Padding { size } => image_with_white_padding(img, size),
+        };
+    }
+    img
+}
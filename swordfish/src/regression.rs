@@ -0,0 +1,264 @@
+use crate::katana::{self, CardOutcome};
+use crate::tesseract::utils::similarity;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use swordfish_common::{error, info, warn};
+
+const BASELINE_FILE: &str = "baseline.json";
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "webp", "gif"];
+
+#[derive(Deserialize, Debug)]
+struct ExpectedCard {
+    name: String,
+    series: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Fixture {
+    bot: String,
+    cards: Vec<ExpectedCard>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CardResult {
+    name: String,
+    series: String,
+    name_correct: bool,
+    series_correct: bool,
+}
+
+#[derive(Default)]
+struct Totals {
+    cards: usize,
+    name_correct: usize,
+    series_correct: usize,
+    name_similarity: f32,
+    series_similarity: f32,
+}
+
+fn is_correct(read: &str, expected: &str) -> bool {
+    read.trim().to_lowercase() == expected.trim().to_lowercase()
+}
+
+fn percent(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f32 / total as f32 * 100.0
+}
+
+///
+/// List the drop images in the directory, sorted by their path.
+///
+pub fn find_images(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(why) => return Err(format!("Failed to read corpus directory: {:?}", why)),
+    };
+    let mut images: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                .unwrap_or(false)
+        })
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+fn load_fixture(image: &Path) -> Result<Fixture, String> {
+    let mut path = image.as_os_str().to_owned();
+    path.push(".toml");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(why) => return Err(format!("Failed to read fixture: {:?}", why)),
+    };
+    match toml::from_str(&content) {
+        Ok(fixture) => Ok(fixture),
+        Err(why) => Err(format!("Failed to parse fixture: {:?}", why)),
+    }
+}
+
+fn load_baseline(path: &Path) -> Option<BTreeMap<String, CardResult>> {
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(baseline) => Some(baseline),
+        Err(why) => {
+            warn!("Failed to parse baseline: {:?}", why);
+            None
+        }
+    }
+}
+
+async fn analyze_image(image: &Path, bot: &str) -> Result<Vec<CardOutcome>, String> {
+    let bytes = match fs::read(image) {
+        Ok(bytes) => bytes,
+        Err(why) => return Err(format!("Failed to read image: {:?}", why)),
+    };
+    let img = katana::decode_image(&bytes)?;
+    katana::analyze_drop_image(img, bot, &None, false, katana::drop_deadline()).await
+}
+
+///
+/// Run the OCR regression harness with the command line arguments following `regression`.
+///
+/// Every image in the corpus needs a `<image>.toml` fixture with the expected cards.
+/// The database is never queried, so the results only reflect OCR quality.
+///
+/// Returns the process exit code, which is non-zero if any field regressed
+/// compared to `baseline.json`.
+///
+pub async fn run(args: &[String]) -> i32 {
+    let corpus = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(corpus) => PathBuf::from(corpus),
+        None => {
+            error!("Usage: swordfish regression <corpus directory> [--update-baseline]");
+            return 2;
+        }
+    };
+    let update_baseline = args.iter().any(|arg| arg == "--update-baseline");
+    let images = match find_images(&corpus) {
+        Ok(images) => images,
+        Err(why) => {
+            error!("{}", why);
+            return 2;
+        }
+    };
+    info!("Running OCR regression on {} images...", images.len());
+    let mut results: BTreeMap<String, CardResult> = BTreeMap::new();
+    let mut totals = Totals::default();
+    let mut failed = 0;
+    for image in images {
+        let file_name = image.file_name().unwrap().to_string_lossy().to_string();
+        let fixture = match load_fixture(&image) {
+            Ok(fixture) => fixture,
+            Err(why) => {
+                warn!("Skipping {}: {}", file_name, why);
+                continue;
+            }
+        };
+        let cards = match analyze_image(&image, &fixture.bot).await {
+            Ok(cards) => cards,
+            Err(why) => {
+                error!("Failed to analyze {}: {}", file_name, why);
+                failed += 1;
+                continue;
+            }
+        };
+        if cards.len() != fixture.cards.len() {
+            warn!(
+                "{}: expected {} cards, got {}",
+                file_name,
+                fixture.cards.len(),
+                cards.len()
+            );
+        }
+        for (i, expected) in fixture.cards.iter().enumerate() {
+            let (name, series) = match cards.get(i) {
+                Some(Ok(card)) => (card.character.name.clone(), card.character.series.clone()),
+                Some(Err(why)) => {
+                    warn!("{}#{}: {}", file_name, i, why);
+                    (String::new(), String::new())
+                }
+                None => (String::new(), String::new()),
+            };
+            let result = CardResult {
+                name_correct: is_correct(&name, &expected.name),
+                series_correct: is_correct(&series, &expected.series),
+                name,
+                series,
+            };
+            totals.cards += 1;
+            totals.name_correct += result.name_correct as usize;
+            totals.series_correct += result.series_correct as usize;
+            totals.name_similarity += similarity(&result.name, &expected.name);
+            totals.series_similarity += similarity(&result.series, &expected.series);
+            if !result.name_correct || !result.series_correct {
+                info!(
+                    "{}#{}: '{}' · '{}' (expected '{}' · '{}')",
+                    file_name, i, result.name, result.series, expected.name, expected.series
+                );
+            }
+            results.insert(format!("{}#{}", file_name, i), result);
+        }
+    }
+    info!(
+        "Name: {}/{} ({:.2}%, mean similarity {:.3})",
+        totals.name_correct,
+        totals.cards,
+        percent(totals.name_correct, totals.cards),
+        totals.name_similarity / totals.cards.max(1) as f32
+    );
+    info!(
+        "Series: {}/{} ({:.2}%, mean similarity {:.3})",
+        totals.series_correct,
+        totals.cards,
+        percent(totals.series_correct, totals.cards),
+        totals.series_similarity / totals.cards.max(1) as f32
+    );
+    if failed > 0 {
+        error!("{} images failed to be analyzed", failed);
+    }
+    let baseline_path = corpus.join(BASELINE_FILE);
+    if update_baseline {
+        let json = serde_json::to_string_pretty(&results).unwrap();
+        if let Err(why) = fs::write(&baseline_path, json) {
+            error!("Failed to write baseline: {:?}", why);
+            return 2;
+        }
+        info!("Baseline written to {}", baseline_path.display());
+        return 0;
+    }
+    let baseline = match load_baseline(&baseline_path) {
+        Some(baseline) => baseline,
+        None => {
+            info!("No baseline found, run with --update-baseline to create one");
+            return if failed > 0 { 1 } else { 0 };
+        }
+    };
+    let mut regressions = 0;
+    let mut fixes = 0;
+    for (key, result) in &results {
+        let old = match baseline.get(key) {
+            Some(old) => old,
+            None => continue,
+        };
+        for (field, was_correct, is_correct, old_read, new_read) in [
+            (
+                "name",
+                old.name_correct,
+                result.name_correct,
+                &old.name,
+                &result.name,
+            ),
+            (
+                "series",
+                old.series_correct,
+                result.series_correct,
+                &old.series,
+                &result.series,
+            ),
+        ] {
+            if was_correct && !is_correct {
+                regressions += 1;
+                error!(
+                    "Regressed {} {}: '{}' -> '{}'",
+                    key, field, old_read, new_read
+                );
+            } else if !was_correct && is_correct {
+                fixes += 1;
+                info!("Fixed {} {}: '{}' -> '{}'", key, field, old_read, new_read);
+            }
+        }
+    }
+    info!("{} fixed, {} regressed", fixes, regressions);
+    if regressions > 0 || failed > 0 {
+        return 1;
+    }
+    0
+}
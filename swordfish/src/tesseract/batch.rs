@@ -0,0 +1,178 @@
+use crate::tesseract::utils::confidence_from_words;
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+use swordfish_common::structs::{OcrConfidence, WordConfidence};
+
+///
+/// A word read by Tesseract with its line and vertical position.
+///
+#[derive(Debug, Clone)]
+pub struct OcrWord {
+    pub text: String,
+    pub confidence: f32,
+    // (block, paragraph, line)
+    pub line: (i32, i32, i32),
+    pub top: i32,
+    pub height: i32,
+}
+
+impl OcrWord {
+    pub fn word_confidence(&self) -> WordConfidence {
+        WordConfidence {
+            text: self.text.clone(),
+            confidence: self.confidence,
+        }
+    }
+}
+
+///
+/// Parse the words with their position and confidence from Tesseract TSV output.
+///
+pub fn parse_tsv(tsv: &str) -> Vec<OcrWord> {
+    let mut words: Vec<OcrWord> = Vec::new();
+    for line in tsv.lines() {
+        // level page_num block_num par_num line_num word_num left top width height conf text
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 12 || columns[0] != "5" {
+            continue;
+        }
+        let numbers: Vec<i32> = match columns[2..10].iter().map(|c| c.parse::<i32>()).collect() {
+            Ok(numbers) => numbers,
+            Err(_) => continue,
+        };
+        let confidence = match columns[10].parse::<f32>() {
+            Ok(conf) => conf,
+            Err(_) => continue,
+        };
+        if confidence < 0.0 || columns[11].trim().is_empty() {
+            continue;
+        }
+        words.push(OcrWord {
+            text: columns[11].to_string(),
+            confidence,
+            line: (numbers[0], numbers[1], numbers[2]),
+            top: numbers[5],
+            height: numbers[7],
+        });
+    }
+    words
+}
+
+///
+/// Stack the images vertically on a white background, separated by `gap` pixels.
+///
+/// Returns the composed image and the vertical range of each image in it.
+///
+pub fn compose(images: &[DynamicImage], gap: u32) -> (DynamicImage, Vec<(u32, u32)>) {
+    let width = images.iter().map(|img| img.width()).max().unwrap_or(1);
+    let height = images.iter().map(|img| img.height() + gap).sum::<u32>() + gap;
+    let mut canvas = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    let mut ranges: Vec<(u32, u32)> = Vec::with_capacity(images.len());
+    let mut y = gap;
+    for img in images {
+        imageops::overlay(&mut canvas, &img.to_rgb8(), 0, y as i64);
+        ranges.push((y, y + img.height()));
+        y += img.height() + gap;
+    }
+    (DynamicImage::ImageRgb8(canvas), ranges)
+}
+
+///
+/// Rebuild the text from the words, one line per text line.
+///
+pub fn join(words: &[&OcrWord]) -> (String, OcrConfidence) {
+    let mut text = String::new();
+    let mut confidences: Vec<WordConfidence> = Vec::new();
+    let mut current_line: Option<(i32, i32, i32)> = None;
+    for word in words {
+        match current_line {
+            Some(line) if line == word.line => text.push(' '),
+            Some(_) => text.push('\n'),
+            None => {}
+        }
+        current_line = Some(word.line);
+        text.push_str(&word.text);
+        confidences.push(word.word_confidence());
+    }
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    (text, confidence_from_words(confidences))
+}
+
+///
+/// Split the words read from a composed image back into the text of each image.
+///
+/// Words belong to the image containing their vertical center.
+///
+pub fn split(words: &[OcrWord], ranges: &[(u32, u32)]) -> Vec<(String, OcrConfidence)> {
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            let words: Vec<&OcrWord> = words
+                .iter()
+                .filter(|word| {
+                    let center = word.top + word.height / 2;
+                    center >= *start as i32 && center < *end as i32
+                })
+                .collect();
+            join(&words)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t200\t300\t-1\t
+4\t1\t1\t1\t1\t0\t10\t12\t150\t20\t-1\t
+5\t1\t1\t1\t1\t1\t10\t12\t60\t20\t96.5\tRaiden
+5\t1\t1\t1\t1\t2\t80\t12\t80\t20\t91\tShogun
+5\t1\t2\t1\t1\t1\t10\t110\t70\t20\t88\tGenshin
+5\t1\t2\t1\t1\t2\t90\t110\t70\t20\t-1\t
+5\t1\t2\t1\t1\t3\t90\t110\t70\t20\t85\tImpact
+5\t1\t2\t1\t2\t1\t10\t135\t40\t20\t12\tnoise";
+
+    #[test]
+    fn parse_tsv_keeps_words() {
+        let words = parse_tsv(TSV);
+        let texts: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(texts, ["Raiden", "Shogun", "Genshin", "Impact", "noise"]);
+        assert_eq!(words[0].confidence, 96.5);
+        assert_eq!(words[0].line, (1, 1, 1));
+        assert_eq!(words[2].line, (2, 1, 1));
+        assert_eq!((words[2].top, words[2].height), (110, 20));
+    }
+
+    #[test]
+    fn join_puts_lines_on_their_own_line() {
+        let words = parse_tsv(TSV);
+        let (text, confidence) = join(&words[2..].iter().collect::<Vec<&OcrWord>>());
+        assert_eq!(text, "Genshin Impact\nnoise\n");
+        assert_eq!(confidence.words.len(), 3);
+    }
+
+    #[test]
+    fn split_by_vertical_center() {
+        let words = parse_tsv(TSV);
+        // The last word's center (145) is in the gap between both images
+        let reads = split(&words, &[(0, 100), (100, 140)]);
+        assert_eq!(reads.len(), 2);
+        assert_eq!(reads[0].0, "Raiden Shogun\n");
+        assert_eq!(reads[1].0, "Genshin Impact\n");
+        let empty = split(&words, &[(300, 400)]);
+        assert_eq!(empty[0].0, "");
+    }
+
+    #[test]
+    fn compose_stacks_images() {
+        let images = vec![
+            DynamicImage::new_rgb8(100, 20),
+            DynamicImage::new_rgb8(80, 30),
+        ];
+        let (composed, ranges) = compose(&images, 10);
+        assert_eq!((composed.width(), composed.height()), (100, 80));
+        assert_eq!(ranges, [(10, 30), (40, 70)]);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::CONFIG;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use swordfish_common::{error, info, trace};
+use tokio::task;
+use tokio::time::{self, Duration};
+
+const DEFAULT_CONFUSIONS: &str = include_str!("confusions.json");
+static CONFUSIONS: OnceLock<Mutex<ConfusionTable>> = OnceLock::new();
+
+///
+/// How often Tesseract read a character (the key) when the text actually had another one.
+///
+/// e.g. `{"0": {"O": 12}}` means "O" was read as "0" 12 times.
+///
+type Table = BTreeMap<String, BTreeMap<String, u64>>;
+
+struct ConfusionTable {
+    table: Table,
+    dirty: bool,
+}
+
+///
+/// Load the confusions from the file, or the default confusions if it doesn't exist.
+///
+/// The default confusions are written to the file if `create` is true.
+///
+pub fn init(path: &str, create: bool) -> Result<(), String> {
+    let content = if Path::new(path).exists() {
+        match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(why) => return Err(format!("Failed to read confusions: {:?}", why)),
+        }
+    } else if create {
+        if let Err(why) = fs::write(path, DEFAULT_CONFUSIONS) {
+            return Err(format!("Failed to write default confusions: {:?}", why));
+        }
+        DEFAULT_CONFUSIONS.to_string()
+    } else {
+        DEFAULT_CONFUSIONS.to_string()
+    };
+    let table: Table = match serde_json::from_str(&content) {
+        Ok(table) => table,
+        Err(why) => return Err(format!("Failed to parse confusions: {:?}", why)),
+    };
+    info!(
+        "Loaded confusions for {} characters from {}",
+        table.len(),
+        path
+    );
+    match CONFUSIONS.set(Mutex::new(ConfusionTable {
+        table,
+        dirty: false,
+    })) {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Confusions are already loaded".to_string()),
+    }
+}
+
+///
+/// Write the confusions back to the file if any were recorded since the last save.
+///
+pub fn save() {
+    let confusions = match CONFUSIONS.get() {
+        Some(confusions) => confusions,
+        None => return,
+    };
+    let mut confusions = confusions.lock().unwrap();
+    if !confusions.dirty {
+        return;
+    }
+    let path = &CONFIG.get().unwrap().analyzer.confusions.path;
+    let json = serde_json::to_string_pretty(&confusions.table).unwrap();
+    match fs::write(path, json) {
+        Ok(_) => confusions.dirty = false,
+        Err(why) => error!("Failed to save confusions: {:?}", why),
+    }
+}
+
+///
+/// Save the recorded confusions periodically rather than after every drop.
+///
+pub fn init_saving() {
+    let interval_secs = CONFIG.get().unwrap().analyzer.confusions.save_interval_secs;
+    task::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            save();
+        }
+    });
+}
+
+///
+/// Escape the text for a PCRE pattern, as the regexes are used by MongoDB.
+///
+/// Any ASCII character other than a letter or digit is escaped, which is always a literal
+/// in PCRE, even in a character class.
+///
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() && !c.is_ascii_alphanumeric() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+///
+/// Align the OCR text with the text it was resolved to and return the substitutions,
+/// as (OCR character, actual text) pairs.
+///
+fn substitutions(ocr: &str, actual: &str) -> Vec<(String, String)> {
+    let a: Vec<char> = ocr.chars().collect();
+    let b: Vec<char> = actual.chars().collect();
+    let mut distances = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            distances[i][j] = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+        }
+    }
+    // Walk back through the table, grouping consecutive edits into one substitution
+    let mut result: Vec<(String, String)> = Vec::new();
+    let mut ocr_part: Vec<char> = Vec::new();
+    let mut actual_part: Vec<char> = Vec::new();
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && a[i - 1] == b[j - 1] && distances[i][j] == distances[i - 1][j - 1] {
+            if !ocr_part.is_empty() || !actual_part.is_empty() {
+                result.push((
+                    ocr_part.drain(..).rev().collect(),
+                    actual_part.drain(..).rev().collect(),
+                ));
+            }
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && j > 0 && distances[i][j] == distances[i - 1][j - 1] + 1 {
+            ocr_part.push(a[i - 1]);
+            actual_part.push(b[j - 1]);
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && distances[i][j] == distances[i - 1][j] + 1 {
+            ocr_part.push(a[i - 1]);
+            i -= 1;
+        } else {
+            actual_part.push(b[j - 1]);
+            j -= 1;
+        }
+    }
+    if !ocr_part.is_empty() || !actual_part.is_empty() {
+        result.push((
+            ocr_part.into_iter().rev().collect(),
+            actual_part.into_iter().rev().collect(),
+        ));
+    }
+    result
+}
+
+///
+/// Record the character-level mistakes of an OCR text resolved to its actual text.
+///
+/// Only single characters read in place of one or two characters are recorded,
+/// since that's what `regexify_text` can use.
+///
+pub fn record(ocr: &str, actual: &str) {
+    let confusions = match CONFUSIONS.get() {
+        Some(confusions) => confusions,
+        None => return,
+    };
+    if !CONFIG.get().unwrap().analyzer.confusions.learn {
+        return;
+    }
+    let mut confusions = confusions.lock().unwrap();
+    for (from, to) in substitutions(ocr, actual) {
+        if from.chars().count() != 1
+            || to.is_empty()
+            || to.chars().count() > 2
+            || from.to_lowercase() == to.to_lowercase()
+        {
+            continue;
+        }
+        trace!("Recording confusion '{}' -> '{}'", from, to);
+        *confusions
+            .table
+            .entry(from)
+            .or_default()
+            .entry(to)
+            .or_insert(0) += 1;
+        confusions.dirty = true;
+    }
+}
+
+///
+/// Build the regex matching the character or the texts it's most often confused with.
+///
+/// Returns `None` if the character has no known confusions.
+///
+pub fn regex_for(c: char) -> Option<String> {
+    let confusions = CONFUSIONS.get()?.lock().unwrap();
+    let config = &CONFIG.get().unwrap().analyzer.confusions;
+    let mut alternatives: Vec<(&String, &u64)> = confusions
+        .table
+        .get(&c.to_string())?
+        .iter()
+        .filter(|(_, count)| **count >= config.min_count)
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    alternatives.sort_by(|a, b| b.1.cmp(a.1));
+    alternatives.truncate(config.max_alternatives);
+    let mut texts: Vec<String> = vec![c.to_string()];
+    texts.extend(alternatives.into_iter().map(|(text, _)| text.clone()));
+    if texts.iter().all(|text| text.chars().count() == 1) {
+        let class: String = texts.iter().map(|text| escape(text)).collect();
+        return Some(format!("[{}]", class));
+    }
+    let group: Vec<String> = texts.iter().map(|text| escape(text)).collect();
+    Some(format!("({})", group.join("|")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn init_defaults() {
+        let _ = CONFIG.set(Config::new());
+        // Missing files load the default confusions
+        let _ = init("tests/missing-confusions.json", false);
+    }
+
+    fn pair(ocr: &str, actual: &str) -> (String, String) {
+        (ocr.to_string(), actual.to_string())
+    }
+
+    #[test]
+    fn substitutions_group_consecutive_edits() {
+        assert_eq!(substitutions("R0m", "Rom"), [pair("0", "o")]);
+        assert_eq!(substitutions("Rarn", "Ram"), [pair("rn", "m")]);
+        assert!(substitutions("Rem", "Rem").is_empty());
+    }
+
+    #[test]
+    fn escape_for_pcre() {
+        assert_eq!(escape("a1"), "a1");
+        assert_eq!(escape("!-]"), "\\!\\-\\]");
+        assert_eq!(escape("é"), "é");
+    }
+
+    #[test]
+    fn regex_for_default_confusions() {
+        init_defaults();
+        assert_eq!(regex_for('0').as_deref(), Some("[0O]"));
+        assert_eq!(regex_for('!').as_deref(), Some("[\\!1Iil]"));
+        assert_eq!(regex_for('m').as_deref(), Some("(m|ra)"));
+        assert_eq!(regex_for('x'), None);
+    }
+}
@@ -0,0 +1,300 @@
+use regex::{NoExpand, Regex};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use swordfish_common::{info, trace};
+
+const DEFAULT_RULES: &str = include_str!("corrections.toml");
+static RULES: OnceLock<Vec<Rule>> = OnceLock::new();
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    #[default]
+    Literal,
+    Regex,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    Anywhere,
+    Start,
+    End,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    #[default]
+    Both,
+    Name,
+    Series,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    #[default]
+    Raw,
+    Clean,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Example {
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RuleDefinition {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    kind: RuleKind,
+    from: String,
+    to: String,
+    #[serde(default)]
+    anchor: Anchor,
+    #[serde(default)]
+    scope: Scope,
+    #[serde(default)]
+    stage: Stage,
+    #[serde(default)]
+    all: bool,
+    #[serde(default)]
+    examples: Vec<Example>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RuleFile {
+    rules: Vec<RuleDefinition>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub description: String,
+    pub scope: Scope,
+    pub stage: Stage,
+    kind: RuleKind,
+    regex: Regex,
+    to: String,
+    all: bool,
+}
+
+impl Rule {
+    fn compile(definition: RuleDefinition) -> Result<Rule, String> {
+        let pattern = match definition.kind {
+            RuleKind::Literal => regex::escape(&definition.from),
+            RuleKind::Regex => definition.from.clone(),
+        };
+        let pattern = match definition.anchor {
+            Anchor::Anywhere => pattern,
+            Anchor::Start => format!("^(?:{})", pattern),
+            Anchor::End => format!("(?:{})$", pattern),
+        };
+        let regex = match Regex::new(&pattern) {
+            Ok(regex) => regex,
+            Err(why) => return Err(format!("Invalid pattern '{}': {}", definition.from, why)),
+        };
+        let rule = Rule {
+            description: definition.description,
+            scope: definition.scope,
+            stage: definition.stage,
+            kind: definition.kind,
+            regex,
+            to: definition.to,
+            all: definition.all,
+        };
+        for example in &definition.examples {
+            let mut output = example.input.clone();
+            rule.apply(&mut output);
+            if output != example.output {
+                return Err(format!(
+                    "'{}' gives {:?} for {:?}, expected {:?}",
+                    definition.from, output, example.input, example.output
+                ));
+            }
+        }
+        Ok(rule)
+    }
+
+    pub fn applies_to(&self, region: &str, stage: Stage) -> bool {
+        if self.stage != stage {
+            return false;
+        }
+        match self.scope {
+            Scope::Both => true,
+            Scope::Name => region == "name",
+            Scope::Series => region == "series",
+        }
+    }
+
+    pub fn apply(&self, text: &mut String) {
+        let replaced = match (self.kind, self.all) {
+            (RuleKind::Literal, false) => self.regex.replace(text, NoExpand(&self.to)),
+            (RuleKind::Literal, true) => self.regex.replace_all(text, NoExpand(&self.to)),
+            (RuleKind::Regex, false) => self.regex.replace(text, self.to.as_str()),
+            (RuleKind::Regex, true) => self.regex.replace_all(text, self.to.as_str()),
+        };
+        let replaced = replaced.into_owned();
+        if replaced != *text {
+            trace!("Applied correction '{}': {}", self.description, replaced);
+            *text = replaced;
+        }
+    }
+}
+
+///
+/// Parse the rules and check every rule against its own examples.
+///
+pub fn parse(content: &str) -> Result<Vec<Rule>, String> {
+    let file: RuleFile = match toml::from_str(content) {
+        Ok(file) => file,
+        Err(why) => return Err(format!("Failed to parse correction rules: {}", why)),
+    };
+    let mut rules: Vec<Rule> = Vec::with_capacity(file.rules.len());
+    let mut errors: Vec<String> = Vec::new();
+    for (i, definition) in file.rules.into_iter().enumerate() {
+        match Rule::compile(definition) {
+            Ok(rule) => rules.push(rule),
+            Err(why) => errors.push(format!("Rule #{}: {}", i + 1, why)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!("Invalid correction rules:\n{}", errors.join("\n")));
+    }
+    Ok(rules)
+}
+
+///
+/// Load the correction rules from the file, or the default rules if it doesn't exist.
+///
+/// The default rules are written to the file if `create` is true.
+///
+pub fn init(path: &str, create: bool) -> Result<(), String> {
+    let content = if Path::new(path).exists() {
+        match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(why) => return Err(format!("Failed to read correction rules: {:?}", why)),
+        }
+    } else if create {
+        if let Err(why) = fs::write(path, DEFAULT_RULES) {
+            return Err(format!(
+                "Failed to write default correction rules: {:?}",
+                why
+            ));
+        }
+        DEFAULT_RULES.to_string()
+    } else {
+        DEFAULT_RULES.to_string()
+    };
+    let rules = parse(&content)?;
+    info!("Loaded {} correction rules from {}", rules.len(), path);
+    match RULES.set(rules) {
+        Ok(_) => Ok(()),
+        Err(_) => Err("Correction rules are already loaded".to_string()),
+    }
+}
+
+///
+/// Apply the rules of the stage to the text read from the region.
+///
+pub fn apply(text: &mut String, region: &str, stage: Stage) {
+    let rules = match RULES.get() {
+        Some(rules) => rules,
+        None => return,
+    };
+    for rule in rules.iter().filter(|r| r.applies_to(region, stage)) {
+        rule.apply(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rules_load() {
+        let rules = parse(DEFAULT_RULES).unwrap();
+        assert!(!rules.is_empty());
+    }
+
+    #[test]
+    fn rule_fields() {
+        let rules = parse(
+            r#"
+            [[rules]]
+            description = "Trailing dot in names"
+            kind = "regex"
+            from = '\.+'
+            to = ""
+            anchor = "end"
+            scope = "name"
+            stage = "clean"
+            examples = [{ input = "Rem..", output = "Rem" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 1);
+        let rule = &rules[0];
+        assert!(rule.applies_to("name", Stage::Clean));
+        assert!(!rule.applies_to("series", Stage::Clean));
+        assert!(!rule.applies_to("name", Stage::Raw));
+        let mut text = "Mr. Rem.".to_string();
+        rule.apply(&mut text);
+        assert_eq!(text, "Mr. Rem");
+    }
+
+    #[test]
+    fn literal_rules_replace_the_first_match_unless_all() {
+        let rules = parse(
+            r#"
+            [[rules]]
+            from = "|"
+            to = "I"
+
+            [[rules]]
+            from = "|"
+            to = "$1"
+            all = true
+            "#,
+        )
+        .unwrap();
+        let mut text = "|| ||".to_string();
+        rules[0].apply(&mut text);
+        assert_eq!(text, "I| ||");
+        rules[1].apply(&mut text);
+        assert_eq!(text, "I$1 $1$1");
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected() {
+        let why = parse(
+            r#"
+            [[rules]]
+            kind = "regex"
+            from = "("
+            to = ""
+            "#,
+        )
+        .unwrap_err();
+        assert!(why.contains("Rule #1"), "{}", why);
+    }
+
+    #[test]
+    fn failing_example_is_rejected() {
+        let why = parse(
+            r#"
+            [[rules]]
+            from = "0"
+            to = "O"
+            examples = [{ input = "R0m", output = "Rem" }]
+            "#,
+        )
+        .unwrap_err();
+        assert!(why.contains("\"ROm\""), "{}", why);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::CONFIG;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use swordfish_common::database::katana as db;
+use swordfish_common::{error, info};
+use tokio::task;
+use tokio::time::{self, Duration};
+
+///
+/// Paths of the user-words and user-patterns files if they're enabled and generated.
+///
+pub fn files() -> Option<(&'static str, &'static str)> {
+    let config = &CONFIG.get().unwrap().analyzer.dictionary;
+    if !config.enabled
+        || !Path::new(&config.words_path).exists()
+        || !Path::new(&config.patterns_path).exists()
+    {
+        return None;
+    }
+    Some((&config.words_path, &config.patterns_path))
+}
+
+fn collect_words(texts: &[String], words: &mut BTreeSet<String>) {
+    for text in texts {
+        for word in text.split_whitespace() {
+            if word.chars().count() < 2 || !word.chars().any(|c| c.is_alphanumeric()) {
+                continue;
+            }
+            words.insert(word.to_string());
+        }
+    }
+}
+
+///
+/// Tesseract pattern of the word with the digits replaced by `\d`, e.g. "2B" -> "\dB".
+///
+/// Only words containing digits get a pattern.
+///
+fn word_pattern(word: &str) -> Option<String> {
+    if !word.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut pattern = String::new();
+    for c in word.chars() {
+        match c {
+            '0'..='9' => pattern.push_str("\\d"),
+            '\\' => pattern.push_str("\\\\"),
+            '*' => pattern.push_str("\\*"),
+            _ => pattern.push(c),
+        }
+    }
+    Some(pattern)
+}
+
+fn write_file(path: &str, lines: &BTreeSet<String>) -> Result<(), String> {
+    // Tesseract may be reading the old file, so replace it in one go.
+    let tmp_path = format!("{}.tmp", path);
+    let mut content = lines.iter().cloned().collect::<Vec<String>>().join("\n");
+    content.push('\n');
+    if let Err(why) = fs::write(&tmp_path, content) {
+        return Err(format!("Failed to write {}: {:?}", tmp_path, why));
+    }
+    match fs::rename(&tmp_path, path) {
+        Ok(_) => Ok(()),
+        Err(why) => Err(format!("Failed to replace {}: {:?}", path, why)),
+    }
+}
+
+///
+/// Generate the user-words and user-patterns files from the characters in the database.
+///
+pub async fn generate() -> Result<(), String> {
+    let config = &CONFIG.get().unwrap().analyzer.dictionary;
+    let names = db::query_distinct("name").await?;
+    let series = db::query_distinct("series").await?;
+    let mut words: BTreeSet<String> = BTreeSet::new();
+    collect_words(&names, &mut words);
+    collect_words(&series, &mut words);
+    let patterns: BTreeSet<String> = words.iter().filter_map(|w| word_pattern(w)).collect();
+    write_file(&config.words_path, &words)?;
+    write_file(&config.patterns_path, &patterns)?;
+    info!(
+        "Generated Tesseract dictionary with {} words and {} patterns",
+        words.len(),
+        patterns.len()
+    );
+    Ok(())
+}
+
+///
+/// Regenerate the dictionary periodically as the database grows.
+///
+/// The database must be initialized before calling this.
+///
+pub fn init() {
+    let refresh_hours = CONFIG.get().unwrap().analyzer.dictionary.refresh_hours;
+    task::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(refresh_hours.max(1) * 60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(why) = generate().await {
+                error!("Failed to generate Tesseract dictionary: {}", why);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_pattern_replaces_digits() {
+        assert_eq!(word_pattern("2B").as_deref(), Some("\\dB"));
+        assert_eq!(word_pattern("3rd").as_deref(), Some("\\drd"));
+        assert_eq!(word_pattern("No.21").as_deref(), Some("No.\\d\\d"));
+    }
+
+    #[test]
+    fn word_pattern_escapes_tesseract_syntax() {
+        assert_eq!(word_pattern("1\\2").as_deref(), Some("\\d\\\\\\d"));
+        assert_eq!(word_pattern("A*1").as_deref(), Some("A\\*\\d"));
+    }
+
+    #[test]
+    fn words_without_digits_have_no_pattern() {
+        assert_eq!(word_pattern("Rem"), None);
+        assert_eq!(word_pattern(""), None);
+    }
+}
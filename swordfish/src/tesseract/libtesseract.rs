@@ -1,17 +1,25 @@
-pub use leptess::{LepTess, Variable};
+use crate::tesseract::batch::{self, OcrWord};
+use crate::tesseract::dictionary;
+use crate::tesseract::utils::confidence_from_words;
+use crate::CONFIG;
+use leptess::capi;
 use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
     panic::catch_unwind,
+    ptr,
     sync::{Arc, Mutex},
     thread,
 };
 use swordfish_common::error;
+use swordfish_common::structs::OcrConfidence;
 use tokio::task;
 
-static mut TESSERACT_VEC: Vec<Arc<Mutex<LepTess>>> = Vec::new();
-static mut TESSERACT_NUMERIC_VEC: Vec<Arc<Mutex<LepTess>>> = Vec::new();
+static mut TESSERACT_VEC: Vec<Arc<Mutex<Engine>>> = Vec::new();
+static mut TESSERACT_NUMERIC_VEC: Vec<Arc<Mutex<Engine>>> = Vec::new();
 
-pub unsafe fn get_tesseract() -> Result<Arc<Mutex<LepTess>>, String> {
-    let lep_tess: Arc<Mutex<LepTess>>;
+pub unsafe fn get_tesseract() -> Result<Arc<Mutex<Engine>>, String> {
+    let lep_tess: Arc<Mutex<Engine>>;
     if TESSERACT_VEC.len() == 0 {
         let ocr = match create_tesseract(false) {
             Ok(ocr) => ocr,
@@ -26,8 +34,8 @@ pub unsafe fn get_tesseract() -> Result<Arc<Mutex<LepTess>>, String> {
     Ok(lep_tess)
 }
 
-pub unsafe fn get_tesseract_numeric() -> Arc<Mutex<LepTess>> {
-    let lep_tess: Arc<Mutex<LepTess>>;
+pub unsafe fn get_tesseract_numeric() -> Arc<Mutex<Engine>> {
+    let lep_tess: Arc<Mutex<Engine>>;
     if TESSERACT_NUMERIC_VEC.len() == 0 {
         for _ in 0..3 {
             task::spawn(async move {
@@ -46,29 +54,212 @@ pub unsafe fn get_tesseract_numeric() -> Arc<Mutex<LepTess>> {
     lep_tess
 }
 
-pub fn create_tesseract(numeric_only: bool) -> Result<LepTess, String> {
-    let mut lep_tess = match LepTess::new(None, "eng") {
-        Ok(lep_tess) => lep_tess,
-        Err(why) => return Err(format!("Failed to initialize Tesseract: {:?}", why)),
-    };
-    lep_tess
-        .set_variable(Variable::TesseditPagesegMode, "6")
-        .unwrap();
-    // Use LSTM only.
-    lep_tess
-        .set_variable(Variable::TesseditOcrEngineMode, "1")
-        .unwrap();
-    // Set 70 as DPI
-    lep_tess
-        .set_variable(Variable::UserDefinedDpi, "70")
-        .unwrap();
-    if numeric_only {
-        match lep_tess.set_variable(Variable::TesseditCharWhitelist, "0123456789") {
-            Ok(_) => (),
-            Err(why) => return Err(format!("Failed to set whitelist: {:?}", why)),
+///
+/// A Tesseract instance using the C API, so any variable can be set by its name.
+///
+/// leptess only sets variables through its `Variable` enum, and initializes Tesseract
+/// without variables.
+///
+pub struct Engine {
+    handle: *mut capi::TessBaseAPI,
+}
+
+// The handle is only used by one thread at a time, behind a mutex.
+unsafe impl Send for Engine {}
+
+fn c_string(value: &str) -> Result<CString, String> {
+    match CString::new(value) {
+        Ok(value) => Ok(value),
+        Err(why) => Err(format!("Invalid Tesseract argument {:?}: {:?}", value, why)),
+    }
+}
+
+///
+/// Copy the text returned by Tesseract and free it.
+///
+unsafe fn take_text(text: *mut c_char) -> Result<String, String> {
+    if text.is_null() {
+        return Err("Tesseract returned no text".to_string());
+    }
+    let result = CStr::from_ptr(text).to_str().map(|text| text.to_string());
+    capi::TessDeleteText(text);
+    match result {
+        Ok(text) => Ok(text),
+        Err(why) => Err(format!("Failed to decode text: {:?}", why)),
+    }
+}
+
+impl Engine {
+    ///
+    /// Initialize Tesseract with the variables, which are set before the language is loaded.
+    ///
+    pub fn new(
+        tessdata: Option<&str>,
+        lang: &str,
+        oem: i32,
+        variables: &[(String, String)],
+    ) -> Result<Engine, String> {
+        let tessdata = match tessdata {
+            Some(tessdata) => Some(c_string(tessdata)?),
+            None => None,
+        };
+        let lang = c_string(lang)?;
+        let mut names = Vec::with_capacity(variables.len());
+        let mut values = Vec::with_capacity(variables.len());
+        for (name, value) in variables {
+            names.push(c_string(name)?);
+            values.push(c_string(value)?);
+        }
+        // Tesseract doesn't modify the strings despite the mutable pointers.
+        let mut name_ptrs: Vec<*mut c_char> = names
+            .iter()
+            .map(|name| name.as_ptr() as *mut c_char)
+            .collect();
+        let mut value_ptrs: Vec<*mut c_char> = values
+            .iter()
+            .map(|value| value.as_ptr() as *mut c_char)
+            .collect();
+        let engine = Engine {
+            handle: unsafe { capi::TessBaseAPICreate() },
         };
+        let result = unsafe {
+            capi::TessBaseAPIInit4(
+                engine.handle,
+                tessdata
+                    .as_ref()
+                    .map_or(ptr::null(), |tessdata| tessdata.as_ptr()),
+                lang.as_ptr(),
+                oem as _,
+                ptr::null_mut(),
+                0,
+                name_ptrs.as_mut_ptr(),
+                value_ptrs.as_mut_ptr(),
+                name_ptrs.len() as _,
+                0,
+            )
+        };
+        match result {
+            0 => Ok(engine),
+            code => Err(format!("Failed to initialize Tesseract: error {}", code)),
+        }
+    }
+
+    ///
+    /// Set a Tesseract variable by its name.
+    ///
+    pub fn set_variable(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let (c_name, c_value) = (c_string(name)?, c_string(value)?);
+        match unsafe {
+            capi::TessBaseAPISetVariable(self.handle, c_name.as_ptr(), c_value.as_ptr())
+        } {
+            0 => Err(format!("Failed to set Tesseract variable {}", name)),
+            _ => Ok(()),
+        }
+    }
+
+    ///
+    /// Set the image to read, in any format supported by Leptonica.
+    ///
+    pub fn set_image_from_mem(&mut self, img: &[u8]) -> Result<(), String> {
+        unsafe {
+            let mut pix = capi::pixReadMem(img.as_ptr(), img.len() as _);
+            if pix.is_null() {
+                return Err("Failed to decode image".to_string());
+            }
+            // Tesseract keeps its own copy of the image.
+            capi::TessBaseAPISetImage2(self.handle, pix);
+            capi::pixDestroy(&mut pix);
+        }
+        Ok(())
+    }
+
+    pub fn get_utf8_text(&mut self) -> Result<String, String> {
+        unsafe { take_text(capi::TessBaseAPIGetUTF8Text(self.handle)) }
+    }
+
+    pub fn get_tsv_text(&mut self, page: i32) -> Result<String, String> {
+        unsafe { take_text(capi::TessBaseAPIGetTsvText(self.handle, page)) }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        unsafe {
+            capi::TessBaseAPIEnd(self.handle);
+            capi::TessBaseAPIDelete(self.handle);
+        }
+    }
+}
+
+///
+/// Override the DPI of the profile for the next image.
+///
+pub fn set_dpi(engine: &mut Engine, dpi: i32) -> Result<(), String> {
+    engine.set_variable("user_defined_dpi", &dpi.to_string())
+}
+
+pub fn create_tesseract(numeric_only: bool) -> Result<Engine, String> {
+    let config = &CONFIG.get().unwrap().tesseract;
+    let profile = match numeric_only {
+        true => &config.numeric,
+        false => &config.text,
+    };
+    let tessdata = match config.tessdata.is_empty() {
+        true => None,
+        false => Some(config.tessdata.as_str()),
+    };
+    let mut variables: Vec<(String, String)> = profile
+        .variables
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    variables.push(("tessedit_pageseg_mode".into(), profile.psm.to_string()));
+    variables.push(("user_defined_dpi".into(), profile.dpi.to_string()));
+    // Tesseract only reads the dictionary when initializing.
+    if !numeric_only {
+        if let Some((words, patterns)) = dictionary::files() {
+            variables.push(("user_words_file".into(), words.into()));
+            variables.push(("user_patterns_file".into(), patterns.into()));
+        }
+    }
+    Engine::new(tessdata, &profile.lang, profile.oem, &variables)
+}
+
+///
+/// Read the text and its confidence from the image set in Tesseract.
+///
+pub fn get_text_with_confidence(engine: &mut Engine) -> Result<(String, OcrConfidence), String> {
+    let text = match engine.get_utf8_text() {
+        Ok(text) => text,
+        Err(why) => return Err(format!("Failed to read text: {:?}", why)),
+    };
+    // Tesseract caches the recognition result, so this doesn't OCR the image again.
+    let tsv = match engine.get_tsv_text(0) {
+        Ok(tsv) => tsv,
+        Err(why) => return Err(format!("Failed to read TSV: {:?}", why)),
+    };
+    let words = batch::parse_tsv(&tsv);
+    Ok((
+        text,
+        confidence_from_words(words.iter().map(|word| word.word_confidence()).collect()),
+    ))
+}
+
+///
+/// Read the words with their position from the image set in Tesseract.
+///
+pub fn get_words(engine: &mut Engine) -> Result<Vec<OcrWord>, String> {
+    Ok(batch::parse_tsv(&get_tsv(engine)?))
+}
+
+///
+/// Read the TSV output of the image set in Tesseract.
+///
+pub fn get_tsv(engine: &mut Engine) -> Result<String, String> {
+    match engine.get_tsv_text(0) {
+        Ok(tsv) => Ok(tsv),
+        Err(why) => Err(format!("Failed to read TSV: {:?}", why)),
     }
-    Ok(lep_tess)
 }
 
 ///
@@ -1,3 +1,8 @@
+pub mod batch;
+pub mod confusions;
+pub mod corrections;
+pub mod dictionary;
 pub mod libtesseract;
 pub mod subprocess;
 pub mod utils;
+pub mod worker;
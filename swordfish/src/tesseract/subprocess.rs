@@ -1,33 +1,130 @@
+use crate::config::TesseractProfile;
+use crate::tesseract::batch::OcrWord;
+use crate::tesseract::utils::confidence_from_words;
+use crate::tesseract::{dictionary, worker};
+use crate::CONFIG;
+use image::DynamicImage;
 pub use rusty_tesseract;
-pub use rusty_tesseract::{Args, Image};
-use std::{collections::HashMap, sync::LazyLock};
-
-static TESSERACT_ARGS: LazyLock<Args> = LazyLock::new(|| Args {
-    lang: "eng".to_string(),
-    config_variables: HashMap::new(),
-    psm: Some(6),
-    dpi: Some(70),
-    oem: Some(1),
-});
-
-static TESSERACT_NUMERIC_ARGS: LazyLock<Args> = LazyLock::new(|| Args {
-    lang: "eng".to_string(),
-    config_variables: HashMap::from([("tessedit_char_whitelist".into(), "0123456789".into())]),
-    psm: Some(6),
-    dpi: Some(70),
-    oem: Some(1),
-});
-
-pub fn image_to_string(image: &Image) -> Result<String, String> {
-    match rusty_tesseract::image_to_string(image, &TESSERACT_ARGS) {
-        Ok(text) => Ok(text),
-        Err(why) => Err(format!("Failed to OCR image: {:?}", why)),
+pub use rusty_tesseract::{Args, DataOutput, Image};
+use swordfish_common::structs::OcrConfidence;
+
+///
+/// Arguments for the Tesseract command from a profile.
+///
+fn profile_args(profile: &TesseractProfile) -> Args {
+    Args {
+        lang: profile.lang.clone(),
+        config_variables: profile
+            .variables
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+        psm: Some(profile.psm),
+        dpi: Some(profile.dpi),
+        oem: Some(profile.oem),
     }
 }
 
-pub fn image_to_numeric_string(image: &Image) -> Result<String, String> {
-    match rusty_tesseract::image_to_string(image, &TESSERACT_NUMERIC_ARGS) {
-        Ok(text) => Ok(text),
-        Err(why) => Err(format!("Failed to OCR image: {:?}", why)),
+///
+/// Arguments for reading text, with the dictionary generated from the database if available.
+///
+fn text_args() -> Args {
+    let mut args = profile_args(&CONFIG.get().unwrap().tesseract.text);
+    if let Some((words, patterns)) = dictionary::files() {
+        args.config_variables
+            .insert("user_words_file".into(), words.into());
+        args.config_variables
+            .insert("user_patterns_file".into(), patterns.into());
+    }
+    args
+}
+
+fn read_args(numeric: bool, dpi: i32) -> Args {
+    let mut args = match numeric {
+        true => profile_args(&CONFIG.get().unwrap().tesseract.numeric),
+        false => text_args(),
+    };
+    args.dpi = Some(dpi);
+    args
+}
+
+fn tesseract_image(img: &DynamicImage) -> Result<Image, String> {
+    match Image::from_dynamic_image(img) {
+        Ok(image) => Ok(image),
+        Err(why) => Err(format!("Failed to write image: {:?}", why)),
     }
 }
+
+fn data_words(image: &Image, args: &Args) -> Result<Vec<OcrWord>, String> {
+    let output = match rusty_tesseract::image_to_data(image, args) {
+        Ok(output) => output,
+        Err(why) => return Err(format!("Failed to OCR image: {:?}", why)),
+    };
+    Ok(output
+        .data
+        .into_iter()
+        // Only words have text.
+        .filter(|data| data.level == 5 && data.conf >= 0.0 && !data.text.trim().is_empty())
+        .map(|data| OcrWord {
+            text: data.text,
+            confidence: data.conf,
+            line: (data.block_num, data.par_num, data.line_num),
+            top: data.top,
+            height: data.height,
+        })
+        .collect())
+}
+
+///
+/// Read the words of the image, with the worker pool if enabled or a new Tesseract process.
+///
+fn read_words(img: &DynamicImage, numeric: bool, dpi: i32) -> Result<Vec<OcrWord>, String> {
+    if CONFIG.get().unwrap().tesseract.workers.enabled {
+        return Ok(worker::recognize(img, numeric, dpi)?.1);
+    }
+    data_words(&tesseract_image(img)?, &read_args(numeric, dpi))
+}
+
+///
+/// Read the text of the image as Tesseract outputs it, with the confidence of its words.
+///
+fn read_text(
+    img: &DynamicImage,
+    numeric: bool,
+    dpi: i32,
+) -> Result<(String, OcrConfidence), String> {
+    let (text, words) = match CONFIG.get().unwrap().tesseract.workers.enabled {
+        true => worker::recognize(img, numeric, dpi)?,
+        false => {
+            let image = tesseract_image(img)?;
+            let args = read_args(numeric, dpi);
+            let text = match rusty_tesseract::image_to_string(&image, &args) {
+                Ok(text) => text,
+                Err(why) => return Err(format!("Failed to OCR image: {:?}", why)),
+            };
+            (text, data_words(&image, &args)?)
+        }
+    };
+    Ok((
+        text,
+        confidence_from_words(words.iter().map(|word| word.word_confidence()).collect()),
+    ))
+}
+
+pub fn image_to_data(img: &DynamicImage, dpi: i32) -> Result<(String, OcrConfidence), String> {
+    read_text(img, false, dpi)
+}
+
+///
+/// Read the words with their position, for splitting a composed image.
+///
+pub fn image_to_words(img: &DynamicImage, dpi: i32) -> Result<Vec<OcrWord>, String> {
+    read_words(img, false, dpi)
+}
+
+pub fn image_to_numeric_data(
+    img: &DynamicImage,
+    dpi: i32,
+) -> Result<(String, OcrConfidence), String> {
+    read_text(img, true, dpi)
+}
@@ -1,9 +1,13 @@
+use crate::tesseract::confusions;
+use crate::tesseract::corrections::{self, Stage};
+use swordfish_common::structs::{OcrConfidence, WordConfidence};
 use swordfish_common::trace;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
-const ALLOWED_CHARS: [char; 14] = [
-    ' ', '-', '.', '!', ':', '(', ')', '\'', '/', '\'', '@', '&', '_', 'é',
-];
+// ASCII punctuation kept in names, other ASCII characters are OCR noise.
+const ALLOWED_CHARS: [char; 12] = [' ', '-', '.', '!', ':', '(', ')', '\'', '/', '@', '&', '_'];
 const REGEX_STRINGS: [&str; 4] = ["[", "]", ")", "("];
 
 fn replace_string(text: &mut String, from: &str, to: &str) -> bool {
@@ -16,126 +20,47 @@ fn replace_string(text: &mut String, from: &str, to: &str) -> bool {
     }
 }
 
-pub fn fix_tesseract_string(text: &mut String) {
+///
+/// Clean up the text read from the region ("name", "series", ...) with the correction rules.
+///
+pub fn fix_tesseract_string(text: &mut String, region: &str) {
     // Remove the \n
     trace!("Text: {}", text);
     if text.ends_with("\n") {
         text.pop();
     }
-    // Workaround for a bug the text
-    // e.g. "We Never Learn\nN" -> "We Never Learn"
-    trace!("Text: {}", text);
-    if text.ends_with("\nN") {
-        text.truncate(text.len() - 2);
-    }
     // Replace first (to prevent "byte index 13 is not a char boundary; it is inside '—' (bytes 11..14)")
     while replace_string(text, "—", "-") {
         trace!("Replacing '—' with '-'");
     }
-    // Workaround for a bug the text
-    trace!("Text: {}", text);
-    if text.starts_with("- ") || text.starts_with("-.") {
-        text.drain(0..2);
-    }
-    // Remove the first character if it is not alphanumeric
-    if !text.starts_with(|c: char| c.is_ascii_alphanumeric()) {
-        text.remove(0);
-    }
-    // Workaround IR -> Ik
-    // Maybe it only occurs if Ik is in the start of the string?
-    // e.g. "IReda" -> "Ikeda"
-    trace!("Text: {}", text);
-    replace_string(text, "IR", "Ik");
-    // Workaround for "A\n"
-    // This is usually the corner of the card
-    trace!("Text: {}", text);
-    replace_string(text, "A\n", "");
-    // Workaround for ' qugnd' -> ' Grand'
-    // As in Grandfather
-    trace!("Text: {}", text);
-    replace_string(text, "\nqugnd", "\nGrand");
-    // Workaround for '“NO'
-    // This is usually the left bottom corner of the card
-    trace!("Text: {}", text);
-    if text.ends_with(r##"“NO"##) {
-        text.drain(text.len() - 4..text.len());
-    }
-    // Workaround for "\n." (and others in the future)
-    let text_clone = text.clone();
-    let mut clone_chars = text_clone.chars();
-    for (i, c) in clone_chars.clone().enumerate() {
-        if c != '\n' {
-            continue;
-        }
-        let prev_char = match clone_chars.nth(i - 1) {
-            Some(c) => c,
-            None => continue,
-        };
-        let mut rm_prev: i8 = 0;
-        trace!("Prev char: {}", prev_char);
-        if ['-'].contains(&prev_char) {
-            rm_prev = 1;
-            text.remove(i - 1);
-        }
-        // Fix for "Asobi ni Iku lo Asobi ni Oide" -> "Asobi ni Iku yo! Asobi ni Oide"
-        if prev_char == 'l' {
-            let prev_prev_char = match clone_chars.nth(i - 2) {
-                Some(c) => c,
-                None => continue,
-            };
-            trace!("Prev prev char: {}", prev_prev_char);
-            if prev_prev_char == 'o' {
-                rm_prev = -1;
-                text.drain(i - 3..i - 1);
-                text.insert_str(i - 2, "yo!")
-            }
-        }
-        let next_char = match clone_chars.nth(i + 1) {
-            Some(c) => c,
-            None => break,
-        };
-        trace!("Next char: {}", next_char);
-        if ['.'].contains(&next_char) {
-            text.remove((i as i8 + 1 - rm_prev) as usize);
-        }
-    }
+    // Compose accents so "é" is one character like in the database
+    *text = text
+        .nfc()
+        .map(|c| match c {
+            '‘' | '’' | '`' => '\'',
+            '–' => '-',
+            _ => c,
+        })
+        .collect();
+    corrections::apply(text, region, Stage::Raw);
     // Replace "\n" with " "
     trace!("Text: {}", text);
     while replace_string(text, "\n", " ") {
         trace!("Replacing '\\n' with ' '");
     }
-    // Remove all non-alphanumeric characters
-    trace!("Text: {}", text);
-    text.retain(|c| ALLOWED_CHARS.contains(&c) || c.is_ascii_alphanumeric());
-    // Fix "mn" -> "III"
-    trace!("Text: {}", text);
-    if text.ends_with("mn") {
-        text.pop();
-        text.pop();
-        text.push_str("III");
-    }
-    // Fix "1ll" -> "III"
-    trace!("Text: {}", text);
-    replace_string(text, "1ll", "III");
-    // Fix "lll" -> "!!!"
-    trace!("Text: {}", text);
-    replace_string(text, "lll", "!!!");
-    // Fix "Il" -> "II" in the end of the string
+    // Remove the ASCII noise, keeping letters of any script and symbols like "♡"
     trace!("Text: {}", text);
-    if text.ends_with("Il") {
-        text.pop();
-        text.pop();
-        text.push_str("II");
-    }
+    text.retain(|c| {
+        ALLOWED_CHARS.contains(&c)
+            || c.is_alphanumeric()
+            || (!c.is_ascii() && !c.is_whitespace() && !c.is_control())
+    });
+    corrections::apply(text, region, Stage::Clean);
     // Replace multiple spaces with one space
     trace!("Text: {}", text);
     while replace_string(text, "  ", " ") {
         trace!("Removing multiple spaces");
     }
-    // Remove the last character if it is a dash
-    if text.ends_with("-") {
-        text.pop();
-    }
     // Workaround if the first character is a space
     trace!("Text: {}", text);
     while text.starts_with(|c: char| c.is_whitespace()) {
@@ -153,8 +78,9 @@ pub fn fix_tesseract_string(text: &mut String) {
 
 pub fn regexify_text(text: &String) -> String {
     let partial_match: bool;
-    let short_text = text.len() < 6;
-    if text.len() > 23 {
+    let length = text.graphemes(true).count();
+    let short_text = length < 6;
+    if length > 23 {
         partial_match = true;
     } else {
         partial_match = false;
@@ -163,21 +89,7 @@ pub fn regexify_text(text: &String) -> String {
     let mut ascii_text = String::new();
     let mut prev_chars: Vec<char> = Vec::new();
     for c in text.chars() {
-        // Here comes the workaround...
-        // The character "0" is sometimes used in place of "O" in names
-        if ['0', 'O'].contains(&c) {
-            ascii_text.push_str("[0O]");
-        } else if ['u', 'v', 'y'].contains(&c) {
-            ascii_text.push_str("[uvy]");
-        } else if ['t'].contains(&c) {
-            ascii_text.push_str("[ti]");
-        } else if ['I', 'l', '!', '1'].contains(&c) {
-            ascii_text.push_str("[Il!1i]");
-        } else if ['o', 'c'].contains(&c) {
-            ascii_text.push_str("[oc]");
-        } else if ['é', 'á', 'd'].contains(&c) {
-            ascii_text.push_str("[éád]");
-        } else if ['.'].contains(&c) {
+        if ['.'].contains(&c) {
             if prev_chars.len() > 3 {
                 let prev_char = prev_chars[prev_chars.len() - 1];
                 let prev_prev_char = prev_chars[prev_chars.len() - 2];
@@ -186,13 +98,10 @@ pub fn regexify_text(text: &String) -> String {
                 }
             }
             ascii_text.push(' ');
-        } else if ['R'].contains(&c) {
-            ascii_text.push_str("[Rk]");
-        } else if ['m'].contains(&c) {
-            ascii_text.push_str("(m|ra)");
-        } else if ['a'].contains(&c) {
-            ascii_text.push_str("[ao]")
-        } else if c.is_ascii_alphanumeric() {
+        } else if let Some(regex) = confusions::regex_for(c) {
+            // e.g. "0" is sometimes read in place of "O" in names
+            ascii_text.push_str(&regex);
+        } else if c.is_alphanumeric() || is_combining_mark(c) {
             ascii_text.push(c);
         } else {
             ascii_text.push(' ');
@@ -209,31 +118,32 @@ pub fn regexify_text(text: &String) -> String {
         let mut request_quantifier: bool = false;
         let mut regex_any: bool = false;
         let mut regex_any_from: usize = 0;
-        for (i, char) in ascii_text.chars().enumerate() {
-            trace!("Char: {}", char);
-            if char == '[' {
+        let graphemes: Vec<&str> = ascii_text.graphemes(true).collect();
+        for (i, grapheme) in graphemes.iter().enumerate() {
+            trace!("Grapheme: {}", grapheme);
+            if *grapheme == "[" {
                 regex_any = true;
                 regex_any_from = i;
                 if i == 0 {
                     request_quantifier = true;
                 }
                 continue;
-            } else if i == ascii_text.len() - 1 {
+            } else if i == graphemes.len() - 1 {
                 regex.push_str(".*");
-                regex.push(char);
+                regex.push_str(grapheme);
                 break;
             }
             if regex_any {
-                if char == ']' {
+                if *grapheme == "]" {
                     regex_any = false;
-                    regex.push_str(&ascii_text[regex_any_from..i + 1]);
+                    regex.push_str(&graphemes[regex_any_from..i + 1].concat());
                     if request_quantifier {
                         regex.push_str(".*");
                     }
                 }
                 continue;
             }
-            regex.push(char);
+            regex.push_str(grapheme);
             if i == 0 {
                 regex.push_str(".*");
             }
@@ -246,7 +156,7 @@ pub fn regexify_text(text: &String) -> String {
     let len = split.clone().count();
     trace!("Partial match: {}", partial_match);
     for (i, word) in split.enumerate() {
-        if word.len() < 2 {
+        if word.graphemes(true).count() < 2 {
             if i > 0 && i < len - 1 {
                 continue;
             }
@@ -290,3 +200,62 @@ pub fn regexify_text(text: &String) -> String {
     trace!("Regex: {}", regex);
     regex
 }
+
+pub fn confidence_from_words(words: Vec<WordConfidence>) -> OcrConfidence {
+    let mean = match words.len() {
+        0 => 0.0,
+        len => words.iter().map(|w| w.confidence).sum::<f32>() / len as f32,
+    };
+    OcrConfidence { mean, words }
+}
+
+///
+/// Case-insensitive similarity (0 - 1) of two strings based on the Levenshtein distance
+/// between their graphemes.
+///
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let a = a.to_lowercase().nfc().collect::<String>();
+    let b = b.to_lowercase().nfc().collect::<String>();
+    let a: Vec<&str> = a.graphemes(true).collect();
+    let b: Vec<&str> = b.graphemes(true).collect();
+    let max_len = a.len().max(b.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+    let mut prev_row: Vec<usize> = (0..=b.len()).collect();
+    let mut row: Vec<usize> = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        row[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            row[j] = (prev_row[j] + 1)
+                .min(row[j - 1] + 1)
+                .min(prev_row[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    1.0 - prev_row[b.len()] as f32 / max_len as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarity_of_texts() {
+        assert_eq!(similarity("Rem", "Rem"), 1.0);
+        assert_eq!(similarity("REM", "rem"), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("Rem", ""), 0.0);
+        assert_eq!(similarity("Ram", "Rem"), 1.0 - 1.0 / 3.0);
+        assert_eq!(similarity("Frieren", "Fern"), 1.0 - 3.0 / 7.0);
+    }
+
+    #[test]
+    fn similarity_compares_graphemes() {
+        // Composed and decomposed accents are the same grapheme
+        assert_eq!(similarity("Léon", "Le\u{301}on"), 1.0);
+        assert_eq!(similarity("Léon", "Leon"), 0.75);
+        assert_eq!(similarity("ルカ", "ルガ"), 0.5);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::tesseract::batch::{self, OcrWord};
+use crate::CONFIG;
+use image::{DynamicImage, ImageFormat};
+use std::env;
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use swordfish_common::{error, info, warn};
+
+// Requests are a profile byte (0 for text, 1 for numeric), the payload length (u32 BE),
+// the DPI (i32 BE) and the PNG image. Responses are a status byte (0 for a result, 1 for an error),
+// the payload length (u32 BE) and the payload. A result is the text length (u32 BE), the UTF-8
+// text and the UTF-8 TSV output, an error is the UTF-8 message.
+const PROFILE_TEXT: u8 = 0;
+const PROFILE_NUMERIC: u8 = 1;
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+static POOL: OnceLock<Vec<Mutex<Option<Worker>>>> = OnceLock::new();
+static NEXT_WORKER: AtomicUsize = AtomicUsize::new(0);
+
+fn read_frame(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+fn write_frame(writer: &mut impl Write, kind: u8, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&[kind])?;
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+///
+/// Read the next request sent to a worker, as (numeric, DPI, PNG image).
+///
+/// Returns `None` once the pool closed the worker's stdin.
+///
+pub fn read_request(reader: &mut impl Read) -> io::Result<Option<(bool, i32, Vec<u8>)>> {
+    match read_frame(reader) {
+        Ok((_, payload)) if payload.len() < 4 => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Request is missing the DPI",
+        )),
+        Ok((profile, mut payload)) => {
+            let png = payload.split_off(4);
+            let dpi = i32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            Ok(Some((profile == PROFILE_NUMERIC, dpi, png)))
+        }
+        Err(why) if why.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(why) => Err(why),
+    }
+}
+
+///
+/// Send the text and TSV output of a request, or why it failed, back to the pool.
+///
+pub fn write_response(
+    writer: &mut impl Write,
+    result: &Result<(String, String), String>,
+) -> io::Result<()> {
+    match result {
+        Ok((text, tsv)) => {
+            let mut payload = (text.len() as u32).to_be_bytes().to_vec();
+            payload.extend_from_slice(text.as_bytes());
+            payload.extend_from_slice(tsv.as_bytes());
+            write_frame(writer, STATUS_OK, &payload)
+        }
+        Err(why) => write_frame(writer, STATUS_ERROR, why.as_bytes()),
+    }
+}
+
+///
+/// Split a result payload into the text and the TSV output.
+///
+fn read_result(payload: &[u8]) -> io::Result<(String, String)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Result is truncated");
+    if payload.len() < 4 {
+        return Err(invalid());
+    }
+    let len = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+    match payload[4..].split_at_checked(len) {
+        Some((text, tsv)) => Ok((
+            String::from_utf8_lossy(text).to_string(),
+            String::from_utf8_lossy(tsv).to_string(),
+        )),
+        None => Err(invalid()),
+    }
+}
+
+///
+/// A `swordfish-ocr-worker` process, killed when dropped.
+///
+/// Its responses are read by a separate thread so reads can time out.
+///
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<io::Result<(u8, Vec<u8>)>>,
+}
+
+///
+/// Read the responses of a worker until its stdout is closed.
+///
+fn forward_responses(mut stdout: BufReader<ChildStdout>) -> Receiver<io::Result<(u8, Vec<u8>)>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let frame = read_frame(&mut stdout);
+        let closed = frame.is_err();
+        if sender.send(frame).is_err() || closed {
+            break;
+        }
+    });
+    receiver
+}
+
+impl Worker {
+    fn spawn() -> Result<Worker, String> {
+        let path = worker_path()?;
+        let mut child = match Command::new(&path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(why) => return Err(format!("Failed to start {}: {:?}", path.display(), why)),
+        };
+        let stdin = child.stdin.take().unwrap();
+        let responses = forward_responses(BufReader::new(child.stdout.take().unwrap()));
+        Ok(Worker {
+            child,
+            stdin,
+            responses,
+        })
+    }
+
+    ///
+    /// Send the image to the worker and wait for its text and TSV output.
+    ///
+    /// An IO error means the worker died or didn't answer in time, a recognition error is
+    /// returned as the inner error.
+    ///
+    fn recognize(
+        &mut self,
+        numeric: bool,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> io::Result<Result<(String, String), String>> {
+        let profile = match numeric {
+            true => PROFILE_NUMERIC,
+            false => PROFILE_TEXT,
+        };
+        write_frame(&mut self.stdin, profile, payload)?;
+        let (status, payload) = match self.responses.recv_timeout(timeout) {
+            Ok(frame) => frame?,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "No response in time",
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Worker exited"))
+            }
+        };
+        match status {
+            STATUS_OK => Ok(Ok(read_result(&payload)?)),
+            _ => Ok(Err(String::from_utf8_lossy(&payload).to_string())),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn worker_path() -> Result<PathBuf, String> {
+    let path = &CONFIG.get().unwrap().tesseract.workers.path;
+    if !path.is_empty() {
+        return Ok(PathBuf::from(path));
+    }
+    match env::current_exe() {
+        Ok(exe) => Ok(exe.with_file_name("swordfish-ocr-worker")),
+        Err(why) => Err(format!("Failed to find the current executable: {:?}", why)),
+    }
+}
+
+///
+/// Start the worker processes.
+///
+/// Workers which fail to start are started again on their first request.
+///
+pub fn init() {
+    let size = CONFIG.get().unwrap().tesseract.workers.size.max(1);
+    let mut pool: Vec<Mutex<Option<Worker>>> = Vec::with_capacity(size);
+    for _ in 0..size {
+        match Worker::spawn() {
+            Ok(worker) => pool.push(Mutex::new(Some(worker))),
+            Err(why) => {
+                error!("Failed to start Tesseract worker: {}", why);
+                pool.push(Mutex::new(None));
+            }
+        }
+    }
+    info!("Started {} Tesseract workers", size);
+    if POOL.set(pool).is_err() {
+        warn!("Tesseract workers are already started");
+    }
+}
+
+///
+/// Read the text and the words of the image with a worker, restarting it if it crashed.
+///
+/// A worker not answering within the OCR timeout is killed and restarted too, since its
+/// result would be abandoned anyway.
+///
+pub fn recognize(
+    img: &DynamicImage,
+    numeric: bool,
+    dpi: i32,
+) -> Result<(String, Vec<OcrWord>), String> {
+    let pool = match POOL.get() {
+        Some(pool) => pool,
+        None => return Err("Tesseract workers aren't started".to_string()),
+    };
+    let mut png: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    if let Err(why) = img.write_to(&mut png, ImageFormat::Png) {
+        return Err(format!("Failed to write image: {:?}", why));
+    }
+    let timeout = Duration::from_millis(CONFIG.get().unwrap().analyzer.timeouts.ocr_ms);
+    let mut payload = dpi.to_be_bytes().to_vec();
+    payload.extend_from_slice(png.get_ref());
+    // Take an idle worker, or wait for the next one in turn if they're all busy
+    let mut slot = match pool.iter().find_map(|slot| slot.try_lock().ok()) {
+        Some(slot) => slot,
+        None => {
+            let index = NEXT_WORKER.fetch_add(1, Ordering::Relaxed) % pool.len();
+            pool[index]
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+    };
+    // Retry once with a new worker if it crashed
+    for _ in 0..2 {
+        if slot.is_none() {
+            *slot = Some(Worker::spawn()?);
+        }
+        match slot.as_mut().unwrap().recognize(numeric, &payload, timeout) {
+            Ok(Ok((text, tsv))) => return Ok((text, batch::parse_tsv(&tsv))),
+            Ok(Err(why)) => return Err(format!("Failed to OCR image: {}", why)),
+            Err(why) if why.kind() == io::ErrorKind::TimedOut => {
+                // Dropping the worker kills it
+                *slot = None;
+                return Err("Tesseract worker timed out and was killed".to_string());
+            }
+            Err(why) => {
+                warn!("Tesseract worker crashed, restarting it: {:?}", why);
+                *slot = None;
+            }
+        }
+    }
+    Err("Tesseract worker crashed twice".to_string())
+}
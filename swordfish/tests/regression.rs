@@ -0,0 +1,17 @@
+use std::path::Path;
+use swordfish::config::Config;
+use swordfish::{regression, CONFIG};
+
+// Reads the bundled corpus with the default config, so it needs Tesseract and the
+// English language data. Run with `cargo test -- --ignored`.
+#[tokio::test]
+#[ignore = "needs Tesseract"]
+async fn corpus_matches_baseline() {
+    CONFIG
+        .set(Config::new())
+        .expect("Failed to register config to static");
+    swordfish::init_analyzer(false).await;
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let code = regression::run(&[corpus.to_string_lossy().to_string()]).await;
+    assert_eq!(code, 0, "OCR regressed compared to the corpus baseline");
+}